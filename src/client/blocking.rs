@@ -0,0 +1,89 @@
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+#[cfg(feature = "regex")]
+use super::ExpectMatch;
+use super::SerialPortClient;
+use super::SerialPortClientBuilder;
+
+/// Synchronous wrapper around `SerialPortClient`
+///
+/// Owns a runtime with a single worker thread that drives the MQTT client,
+/// so it can be used from a plain `#[test]` or a C ABI shim. Its methods
+/// must not be called from inside another tokio runtime.
+pub struct BlockingSerialPortClient {
+    /// Runtime driving the client
+    runtime: Runtime,
+    /// Asynchronous client
+    client: SerialPortClient,
+}
+
+impl BlockingSerialPortClient {
+    // ------------------------------------------------------------------------
+
+    /// Build the client and wait until it is connected to the broker
+    pub fn connect(builder: SerialPortClientBuilder, timeout: Duration) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("serial-port-client")
+            .enable_all()
+            .build()?;
+
+        let client = runtime.block_on(async {
+            let client = builder.build()?;
+            tokio::time::timeout(timeout, client.connected())
+                .await
+                .map_err(|_| {
+                    anyhow::anyhow!("Not connected to the broker within {:?}", timeout)
+                })??;
+            Ok::<_, anyhow::Error>(client)
+        })?;
+
+        Ok(Self { runtime, client })
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Asynchronous client, to be used with `runtime`
+    pub fn client(&self) -> &SerialPortClient {
+        &self.client
+    }
+
+    /// Runtime driving the client
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Send bytes through the serial port
+    pub fn send(&self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.runtime
+            .block_on(self.client.send(Bytes::copy_from_slice(bytes)))
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Read the received data, waiting up to `timeout` if none is pending
+    pub fn read(&self, timeout: Duration) -> anyhow::Result<Bytes> {
+        self.runtime.block_on(self.client.read(timeout))
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Read a line, without its line ending
+    pub fn read_line(&self, timeout: Duration) -> anyhow::Result<String> {
+        self.runtime.block_on(self.client.read_line(timeout))
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Wait for the regular expression `pattern` to match the received data
+    #[cfg(feature = "regex")]
+    pub fn expect(&self, pattern: &str, timeout: Duration) -> anyhow::Result<ExpectMatch> {
+        self.runtime.block_on(self.client.expect(pattern, timeout))
+    }
+
+    // ------------------------------------------------------------------------
+}
@@ -1,17 +1,149 @@
 use crate::client::SerialPortClient;
-use pza_toolkit::config::IPEndpointConfig;
-use pza_toolkit::rumqtt::client::init_client;
+use rumqttc::AsyncClient;
+use rumqttc::MqttOptions;
+use rumqttc::TlsConfiguration;
+use rumqttc::Transport;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error as ThisError;
+
+/// Keep alive of the connections opened by the client
+const KEEP_ALIVE: Duration = Duration::from_secs(5);
+
+/// Capacity of the request channel between the MQTT client and its event loop
+const REQUEST_CHANNEL_CAPACITY: usize = 100;
+
+/// Default capacity of the broadcast channels of the client
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 32;
+
+/// Broker used when no endpoint is given
+const DEFAULT_BROKER_ADDR: &str = "127.0.0.1";
+const DEFAULT_BROKER_PORT: u16 = 1883;
+
+/// Address of the MQTT broker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerEndpoint {
+    /// Host name or IP address
+    pub addr: String,
+    /// TCP port
+    pub port: u16,
+}
+
+impl BrokerEndpoint {
+    /// Create a new broker endpoint
+    pub fn new<A: Into<String>>(addr: A, port: u16) -> Self {
+        Self {
+            addr: addr.into(),
+            port,
+        }
+    }
+}
+
+impl Default for BrokerEndpoint {
+    fn default() -> Self {
+        Self::new(DEFAULT_BROKER_ADDR, DEFAULT_BROKER_PORT)
+    }
+}
+
+#[cfg(feature = "server")]
+impl From<pza_toolkit::config::IPEndpointConfig> for BrokerEndpoint {
+    fn from(endpoint: pza_toolkit::config::IPEndpointConfig) -> Self {
+        Self::new(endpoint.addr, endpoint.port)
+    }
+}
+
+/// Errors returned by `SerialPortClientBuilder::build`
+#[derive(ThisError, Debug)]
+pub enum SerialPortClientBuilderError {
+    #[error("No instance name given, use with_power_supply_name")]
+    MissingInstanceName,
+    #[error("Failed to read TLS file '{path}': {source}")]
+    TlsFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+/// TLS settings of the broker connection
+#[derive(Debug, Clone)]
+pub struct ClientTlsConfig {
+    /// PEM encoded CA certificate(s) used to verify the broker
+    pub ca: Vec<u8>,
+    /// PEM encoded client certificate and private key, for mutual TLS
+    pub client_auth: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl ClientTlsConfig {
+    // ------------------------------------------------------------------------
+
+    /// Create a TLS configuration from a PEM encoded CA
+    pub fn new(ca: Vec<u8>) -> Self {
+        Self {
+            ca,
+            client_auth: None,
+        }
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Authenticate the client with a PEM encoded certificate and private key
+    pub fn with_client_auth(mut self, cert: Vec<u8>, key: Vec<u8>) -> Self {
+        self.client_auth = Some((cert, key));
+        self
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Load the CA and optional client certificate/key from PEM files
+    pub fn from_files<P: AsRef<Path>>(
+        ca: P,
+        client_auth: Option<(P, P)>,
+    ) -> Result<Self, SerialPortClientBuilderError> {
+        let mut config = Self::new(read_pem(ca.as_ref())?);
+        if let Some((cert, key)) = client_auth {
+            config = config.with_client_auth(read_pem(cert.as_ref())?, read_pem(key.as_ref())?);
+        }
+        Ok(config)
+    }
+
+    // ------------------------------------------------------------------------
+}
+
+/// Read a PEM file for the TLS configuration
+fn read_pem(path: &Path) -> Result<Vec<u8>, SerialPortClientBuilderError> {
+    std::fs::read(path).map_err(|source| SerialPortClientBuilderError::TlsFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
 
 /// Builder pattern for creating SerialPortClient instances
 pub struct SerialPortClientBuilder {
     /// Name of the instance
     pub instance_name: Option<String>,
 
-    /// MQTT broker configuration
-    pub ip: Option<IPEndpointConfig>,
+    /// MQTT broker endpoint (localhost:1883 if not set)
+    pub ip: Option<BrokerEndpoint>,
+
+    /// MQTT username and password
+    pub credentials: Option<(String, String)>,
+
+    /// MQTT client id (random if not set)
+    pub client_id: Option<String>,
+
+    /// TLS settings, plain TCP if not set
+    pub tls: Option<ClientTlsConfig>,
 
     /// Enable transmission monitoring
     pub enable_tx_monitoring: bool,
+
+    /// Enable structured rx chunks (timestamp and sequence number)
+    pub enable_rx_chunks: bool,
+
+    /// Capacity of the broadcast channels (rx, tx, chunks...), a slower
+    /// consumer lags and loses data
+    pub channel_capacity: usize,
 }
 
 impl Default for SerialPortClientBuilder {
@@ -19,7 +151,12 @@ impl Default for SerialPortClientBuilder {
         Self {
             instance_name: None,
             ip: None,
+            credentials: None,
+            client_id: None,
+            tls: None,
             enable_tx_monitoring: false, // Explicitly set to false
+            enable_rx_chunks: false,
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
         }
     }
 }
@@ -28,8 +165,30 @@ impl SerialPortClientBuilder {
     // ------------------------------------------------------------------------
 
     /// Create a new builder from broker configuration
-    pub fn with_ip(mut self, ip: IPEndpointConfig) -> Self {
-        self.ip = Some(ip);
+    pub fn with_ip<E: Into<BrokerEndpoint>>(mut self, ip: E) -> Self {
+        self.ip = Some(ip.into());
+        self
+    }
+
+    /// Authenticate on the broker with a username and password
+    pub fn with_credentials<A: Into<String>, B: Into<String>>(
+        mut self,
+        username: A,
+        password: B,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Set the MQTT client id, also used for the presence of the client
+    pub fn with_client_id<A: Into<String>>(mut self, client_id: A) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    /// Connect to the broker over TLS
+    pub fn with_tls(mut self, tls: ClientTlsConfig) -> Self {
+        self.tls = Some(tls);
         self
     }
 
@@ -46,17 +205,59 @@ impl SerialPortClientBuilder {
         self
     }
 
+    /// Subscribe to the structured rx chunks topic
+    pub fn enable_rx_chunks(mut self, enable: bool) -> Self {
+        self.enable_rx_chunks = enable;
+        self
+    }
+
+    /// Set the capacity of the broadcast channels (at least 1)
+    pub fn with_channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = capacity.max(1);
+        self
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// MQTT options of the connection described by this builder
+    pub(crate) fn mqtt_options(&self) -> MqttOptions {
+        let client_id = self.client_id.clone().unwrap_or_else(|| {
+            format!("serial-port-{}", crate::payload::generate_random_string(8))
+        });
+        let broker = self.ip.clone().unwrap_or_default();
+
+        let mut options = MqttOptions::new(client_id, broker.addr, broker.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username.clone(), password.clone());
+        }
+        if let Some(tls) = &self.tls {
+            options.set_transport(Transport::Tls(TlsConfiguration::Simple {
+                ca: tls.ca.clone(),
+                alpn: None,
+                client_auth: tls.client_auth.clone(),
+            }));
+        }
+        options
+    }
+
     // ------------------------------------------------------------------------
 
     /// Build the SerialPortClient instance
     pub fn build(self) -> anyhow::Result<SerialPortClient> {
-        let (client, event_loop) = init_client("serial-port");
+        let instance_name = self
+            .instance_name
+            .clone()
+            .ok_or(SerialPortClientBuilderError::MissingInstanceName)?;
+        let (client, event_loop) = AsyncClient::new(self.mqtt_options(), REQUEST_CHANNEL_CAPACITY);
 
         Ok(SerialPortClient::new_with_client(
-            self.instance_name.unwrap(),
+            instance_name,
             client,
             event_loop,
             self.enable_tx_monitoring,
+            self.enable_rx_chunks,
+            self.channel_capacity,
         ))
     }
 }
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+/// First delay before reconnecting to the broker
+pub(crate) const RECONNECT_BASE_DELAY: Duration = Duration::from_millis(100);
+
+/// Maximum delay between two reconnection attempts
+pub(crate) const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// State of the connection between the client and the broker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// First connection in progress
+    Connecting,
+    /// Connected, subscriptions have been (re)sent
+    Connected,
+    /// Connection lost, next attempt after `retry_in`
+    Reconnecting {
+        /// Cause of the disconnection
+        error: String,
+        /// Number of failed attempts since the last successful connection
+        attempt: u32,
+        /// Delay before the next attempt
+        retry_in: Duration,
+    },
+}
+
+/// Delay before the given reconnection attempt (exponential backoff)
+pub(crate) fn backoff(attempt: u32) -> Duration {
+    RECONNECT_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(RECONNECT_MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_attempts_start_at_the_base_delay() {
+        assert_eq!(backoff(0), RECONNECT_BASE_DELAY);
+        assert_eq!(backoff(1), RECONNECT_BASE_DELAY);
+    }
+
+    #[test]
+    fn delay_doubles_with_each_attempt() {
+        assert_eq!(backoff(2), RECONNECT_BASE_DELAY * 2);
+        assert_eq!(backoff(3), RECONNECT_BASE_DELAY * 4);
+        assert_eq!(backoff(5), RECONNECT_BASE_DELAY * 16);
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        assert_eq!(backoff(20), RECONNECT_MAX_DELAY);
+        assert_eq!(backoff(u32::MAX), RECONNECT_MAX_DELAY);
+    }
+}
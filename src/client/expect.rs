@@ -0,0 +1,424 @@
+use bytes::Buf;
+use bytes::Bytes;
+use bytes::BytesMut;
+#[cfg(feature = "regex")]
+use regex::bytes::Regex;
+#[cfg(feature = "regex")]
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use super::SerialPortClient;
+
+/// Result of a successful `expect`
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct ExpectMatch {
+    /// Text received before the match
+    pub before: String,
+    /// Matched text
+    pub matched: String,
+    /// Capture groups, index 0 is the whole match
+    pub captures: Vec<Option<String>>,
+    /// Named capture groups that participated in the match
+    pub named: HashMap<String, String>,
+}
+
+/// Most received data kept for the expect helpers, the oldest is dropped beyond
+const RX_BUFFER_LIMIT: usize = 1024 * 1024;
+
+/// Received data not consumed by the expect helpers yet
+///
+/// Fed by the client task as the data arrives, so nothing is lost while no
+/// helper runs. Shared by the clones of a client, so helpers called from
+/// different places consume the same stream.
+pub(crate) struct RxBuffer {
+    /// Pending data
+    pending: std::sync::Mutex<Pending>,
+    /// Wakes the helper waiting for data
+    received: Notify,
+    /// Held by the running helper, concurrent helpers consume the data in turn
+    reader: Mutex<()>,
+    /// Most pending data kept
+    limit: usize,
+}
+
+/// Pending data of an `RxBuffer`
+#[derive(Default)]
+struct Pending {
+    /// Data not consumed yet
+    data: BytesMut,
+    /// Number of times the oldest data was dropped to respect the limit
+    trims: u64,
+}
+
+impl RxBuffer {
+    // ------------------------------------------------------------------------
+
+    /// Create an empty buffer
+    pub(crate) fn new() -> Self {
+        Self::with_limit(RX_BUFFER_LIMIT)
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Create an empty buffer keeping at most `limit` bytes
+    fn with_limit(limit: usize) -> Self {
+        Self {
+            pending: std::sync::Mutex::new(Pending::default()),
+            received: Notify::new(),
+            reader: Mutex::new(()),
+            limit,
+        }
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Lock the pending data
+    fn pending(&self) -> std::sync::MutexGuard<'_, Pending> {
+        // The data stays consistent even if a holder panicked
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Append received data, dropping the oldest data beyond the limit
+    pub(crate) fn push(&self, chunk: &[u8]) {
+        {
+            let mut pending = self.pending();
+            pending.data.extend_from_slice(chunk);
+            let excess = pending.data.len().saturating_sub(self.limit);
+            if excess > 0 {
+                pending.data.advance(excess);
+                pending.trims += 1;
+            }
+        }
+        self.received.notify_one();
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Wait for new data until the deadline
+    ///
+    /// Only the helper holding `reader` waits, so the permit `notify_one`
+    /// stores when nobody waits is never lost.
+    async fn wait(&self, deadline: Instant) -> anyhow::Result<()> {
+        tokio::time::timeout_at(deadline, self.received.notified())
+            .await
+            .map_err(|_| anyhow::anyhow!("Timeout"))
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Read the pending data, waiting for some until the deadline
+    async fn read(&self, deadline: Instant) -> anyhow::Result<Bytes> {
+        let _reader = self.reader.lock().await;
+        loop {
+            {
+                let mut pending = self.pending();
+                if !pending.data.is_empty() {
+                    let len = pending.data.len();
+                    return Ok(pending.data.split_to(len).freeze());
+                }
+            }
+            self.wait(deadline).await?;
+        }
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Read until `delimiter` is received, the delimiter is included
+    ///
+    /// Each new chunk is searched from where the previous search stopped,
+    /// keeping the overlap a delimiter split across chunks needs. The search
+    /// starts over if the oldest data was dropped meanwhile.
+    async fn read_until(&self, delimiter: &[u8], deadline: Instant) -> anyhow::Result<Bytes> {
+        let _reader = self.reader.lock().await;
+        let mut searched = 0;
+        let mut trims = None;
+        loop {
+            {
+                let mut pending = self.pending();
+                if trims != Some(pending.trims) {
+                    trims = Some(pending.trims);
+                    searched = 0;
+                }
+                if let Some(pos) = pending.data[searched..]
+                    .windows(delimiter.len())
+                    .position(|window| window == delimiter)
+                {
+                    return Ok(pending
+                        .data
+                        .split_to(searched + pos + delimiter.len())
+                        .freeze());
+                }
+                searched = (pending.data.len() + 1).saturating_sub(delimiter.len());
+            }
+            self.wait(deadline).await?;
+        }
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Wait for `regex` to match the pending data, consuming it up to the match end
+    #[cfg(feature = "regex")]
+    async fn expect(&self, regex: &Regex, deadline: Instant) -> anyhow::Result<ExpectMatch> {
+        let _reader = self.reader.lock().await;
+        loop {
+            {
+                let mut pending = self.pending();
+                if let Some(result) = Self::match_regex(&mut pending.data, regex) {
+                    return Ok(result);
+                }
+            }
+            self.wait(deadline).await?;
+        }
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Match `regex` against `data`, consuming it up to the match end
+    #[cfg(feature = "regex")]
+    fn match_regex(data: &mut BytesMut, regex: &Regex) -> Option<ExpectMatch> {
+        let captures = regex.captures(&data[..])?;
+        let whole = captures.get(0).expect("group 0 always participates");
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        let result = ExpectMatch {
+            before: text(&data[..whole.start()]),
+            matched: text(whole.as_bytes()),
+            captures: captures
+                .iter()
+                .map(|group| group.map(|group| text(group.as_bytes())))
+                .collect(),
+            named: regex
+                .capture_names()
+                .flatten()
+                .filter_map(|name| {
+                    captures
+                        .name(name)
+                        .map(|group| (name.to_string(), text(group.as_bytes())))
+                })
+                .collect(),
+        };
+        let end = whole.end();
+        data.advance(end);
+        Some(result)
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Drop the pending data
+    fn clear(&self) {
+        self.pending().data.clear();
+    }
+
+    // ------------------------------------------------------------------------
+}
+
+impl SerialPortClient {
+    // ------------------------------------------------------------------------
+
+    /// Read the received data, waiting up to `timeout` if none is pending
+    pub async fn read(&self, timeout: Duration) -> anyhow::Result<Bytes> {
+        let deadline = Instant::now() + timeout;
+        self.rx_buffer
+            .read(deadline)
+            .await
+            .map_err(|e| anyhow::anyhow!("No data received within {:?}: {}", timeout, e))
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Read until `delimiter` is received, the delimiter is included
+    pub async fn read_until(&self, delimiter: &[u8], timeout: Duration) -> anyhow::Result<Bytes> {
+        if delimiter.is_empty() {
+            return Err(anyhow::anyhow!("Empty delimiter"));
+        }
+        let deadline = Instant::now() + timeout;
+        self.rx_buffer
+            .read_until(delimiter, deadline)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "{:?} not received within {:?}: {}",
+                    String::from_utf8_lossy(delimiter),
+                    timeout,
+                    e
+                )
+            })
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Read a line, without its line ending (`\n` or `\r\n`)
+    pub async fn read_line(&self, timeout: Duration) -> anyhow::Result<String> {
+        let line = self.read_until(b"\n", timeout).await?;
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        Ok(String::from_utf8_lossy(line).into_owned())
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Wait for the regular expression `pattern` to match the received data
+    ///
+    /// The data up to the end of the match is consumed.
+    #[cfg(feature = "regex")]
+    pub async fn expect(&self, pattern: &str, timeout: Duration) -> anyhow::Result<ExpectMatch> {
+        let regex = Regex::new(pattern)?;
+        let deadline = Instant::now() + timeout;
+        self.rx_buffer
+            .expect(&regex, deadline)
+            .await
+            .map_err(|e| anyhow::anyhow!("'{}' not matched within {:?}: {}", pattern, timeout, e))
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Send `bytes` and read the answer until `until` is received
+    ///
+    /// Data received before the command is dropped, so the answer is not
+    /// mixed with older output.
+    pub async fn transact(
+        &self,
+        bytes: Bytes,
+        until: &[u8],
+        timeout: Duration,
+    ) -> anyhow::Result<Bytes> {
+        self.clear_rx_buffer().await;
+        self.send(bytes).await?;
+        self.read_until(until, timeout).await
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Drop the data received but not consumed by the expect helpers
+    pub async fn clear_rx_buffer(&self) {
+        self.rx_buffer.clear();
+    }
+
+    // ------------------------------------------------------------------------
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn deadline() -> Instant {
+        Instant::now() + TIMEOUT
+    }
+
+    fn pending_data(buffer: &RxBuffer) -> Vec<u8> {
+        buffer.pending().data.to_vec()
+    }
+
+    #[tokio::test]
+    async fn read_until_keeps_the_rest() {
+        let buffer = RxBuffer::new();
+        buffer.push(b"one\ntwo\nthr");
+        assert_eq!(buffer.read_until(b"\n", deadline()).await.unwrap(), "one\n");
+        assert_eq!(buffer.read_until(b"\n", deadline()).await.unwrap(), "two\n");
+        assert_eq!(pending_data(&buffer), b"thr");
+    }
+
+    #[tokio::test]
+    async fn read_until_finds_a_delimiter_split_across_chunks() {
+        let buffer = Arc::new(RxBuffer::new());
+        let reader = tokio::spawn({
+            let buffer = buffer.clone();
+            async move { buffer.read_until(b"\r\n", deadline()).await }
+        });
+        for chunk in [&b"login"[..], b": ro", b"ot\r", b"\nnext"] {
+            tokio::task::yield_now().await;
+            buffer.push(chunk);
+        }
+        assert_eq!(reader.await.unwrap().unwrap(), "login: root\r\n");
+        assert_eq!(pending_data(&buffer), b"next");
+    }
+
+    #[tokio::test]
+    async fn read_until_times_out_and_keeps_the_data() {
+        let buffer = RxBuffer::new();
+        buffer.push(b"partial");
+        assert!(buffer.read_until(b"\n", deadline()).await.is_err());
+        assert_eq!(pending_data(&buffer), b"partial");
+    }
+
+    #[tokio::test]
+    async fn data_received_between_reads_is_kept() {
+        let buffer = RxBuffer::new();
+        for _ in 0..1000 {
+            buffer.push(b"x");
+        }
+        buffer.push(b"\n");
+        let line = buffer.read_until(b"\n", deadline()).await.unwrap();
+        assert_eq!(line.len(), 1001);
+    }
+
+    #[tokio::test]
+    async fn oldest_data_is_dropped_beyond_the_limit() {
+        let buffer = RxBuffer::with_limit(4);
+        buffer.push(b"a\nbcd");
+        assert_eq!(pending_data(&buffer), b"\nbcd");
+        buffer.push(b"e");
+        buffer.push(b"\n");
+        assert_eq!(buffer.read_until(b"\n", deadline()).await.unwrap(), "cde\n");
+    }
+
+    #[tokio::test]
+    async fn read_returns_the_pending_data() {
+        let buffer = RxBuffer::new();
+        assert!(buffer.read(deadline()).await.is_err());
+        buffer.push(b"one");
+        buffer.push(b"two");
+        assert_eq!(buffer.read(deadline()).await.unwrap(), "onetwo");
+        assert!(pending_data(&buffer).is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[tokio::test]
+    async fn expect_reports_the_match_and_captures() {
+        let buffer = RxBuffer::new();
+        buffer.push(b"boot\nversion 1.");
+        buffer.push(b"2 ready\n");
+        let regex = Regex::new(r"version (?P<major>\d+)\.(\d+)").unwrap();
+        let result = buffer.expect(&regex, deadline()).await.unwrap();
+        assert_eq!(result.before, "boot\n");
+        assert_eq!(result.matched, "version 1.2");
+        assert_eq!(
+            result.captures,
+            vec![
+                Some("version 1.2".to_string()),
+                Some("1".to_string()),
+                Some("2".to_string())
+            ]
+        );
+        assert_eq!(result.named.get("major").map(String::as_str), Some("1"));
+        assert_eq!(pending_data(&buffer), b" ready\n");
+    }
+
+    #[cfg(feature = "regex")]
+    #[tokio::test]
+    async fn expect_times_out_without_a_match() {
+        let buffer = RxBuffer::new();
+        buffer.push(b"nothing here");
+        let regex = Regex::new("ready").unwrap();
+        assert!(buffer.expect(&regex, deadline()).await.is_err());
+        assert_eq!(pending_data(&buffer), b"nothing here");
+    }
+
+    #[test]
+    fn clear_drops_the_pending_data() {
+        let buffer = RxBuffer::new();
+        buffer.push(b"pending");
+        buffer.clear();
+        assert!(pending_data(&buffer).is_empty());
+    }
+}
@@ -1,39 +1,110 @@
 use bytes::Bytes;
 // use dioxus::html::sub;
-use pza_toolkit::rumqtt::client::RumqttCustomAsyncClient;
 use rumqttc::AsyncClient;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::broadcast;
+use tokio::sync::watch;
 
+use crate::payload::BytesPayload;
+use crate::payload::ErrorPayload;
+use crate::payload::InstanceInfo;
+use crate::payload::LineSettingsPayload;
+use crate::payload::Presence;
+use crate::payload::StatsPayload;
+use crate::payload::Status;
+use crate::payload::StatusPayload;
+use crate::payload::TxAckPayload;
+use crate::Topics;
+
+#[cfg(feature = "blocking")]
+mod blocking;
 pub mod builder;
+mod connection;
+mod expect;
+mod rx_stream;
+mod settings;
+pub mod stream;
+#[cfg(feature = "blocking")]
+pub use blocking::BlockingSerialPortClient;
+pub use builder::BrokerEndpoint;
+pub use builder::ClientTlsConfig;
 pub use builder::SerialPortClientBuilder;
+pub use builder::SerialPortClientBuilderError;
+pub use connection::ConnectionState;
+#[cfg(feature = "regex")]
+pub use expect::ExpectMatch;
+pub use rx_stream::RxEvent;
+pub use rx_stream::RxStream;
+pub use stream::SerialPortStream;
+
+/// Time without new registry entry after which the discovery ends
+const DISCOVER_SETTLE_DELAY: Duration = Duration::from_millis(200);
 
 /// Client for interacting with a power supply via MQTT
 pub struct SerialPortClient {
     /// Name of the serial port instance
     pub instance_name: String,
 
+    /// Identifier of this client, used for its presence topic
+    client_id: String,
+
     /// MQTT client
-    mqtt_client: RumqttCustomAsyncClient,
+    mqtt_client: AsyncClient,
 
     /// Channel for receiving output current updates
     rx_channel: (broadcast::Sender<Bytes>, broadcast::Receiver<Bytes>),
     tx_channel: (broadcast::Sender<Bytes>, broadcast::Receiver<Bytes>),
 
-    /// Topic for receiving MQTT messages
-    topic_rx: String,
-    topic_tx: String,
+    /// Channel for structured rx chunks (timestamp and sequence number)
+    rx_chunks_channel: broadcast::Sender<BytesPayload>,
+
+    /// Channel for acknowledgements of confirmed tx commands
+    tx_ack_channel: broadcast::Sender<TxAckPayload>,
+    /// Channel for errors reported by the server
+    error_channel: broadcast::Sender<ErrorPayload>,
+    /// Channel for the line settings applied by the server
+    settings_channel: broadcast::Sender<LineSettingsPayload>,
+
+    /// Latest statistics published by the server
+    stats_channel: Arc<watch::Sender<Option<StatsPayload>>>,
+
+    /// Latest lifecycle status published by the server
+    status_channel: Arc<watch::Sender<Option<StatusPayload>>>,
+
+    /// Latest presence of the instance runner
+    presence_channel: Arc<watch::Sender<Option<Presence>>>,
+
+    /// State of the connection to the broker
+    connection_channel: Arc<watch::Sender<ConnectionState>>,
+
+    /// Received data buffered for the expect helpers
+    rx_buffer: Arc<expect::RxBuffer>,
+
+    /// Topics of the instance
+    topics: Topics,
 }
 
 impl Clone for SerialPortClient {
     fn clone(&self) -> Self {
         Self {
             instance_name: self.instance_name.clone(),
+            client_id: self.client_id.clone(),
             mqtt_client: self.mqtt_client.clone(),
             rx_channel: (self.rx_channel.0.clone(), self.rx_channel.1.resubscribe()),
             tx_channel: (self.tx_channel.0.clone(), self.tx_channel.1.resubscribe()),
+            rx_chunks_channel: self.rx_chunks_channel.clone(),
+            tx_ack_channel: self.tx_ack_channel.clone(),
+            error_channel: self.error_channel.clone(),
+            settings_channel: self.settings_channel.clone(),
+            stats_channel: self.stats_channel.clone(),
+            status_channel: self.status_channel.clone(),
+            presence_channel: self.presence_channel.clone(),
+            connection_channel: self.connection_channel.clone(),
+            rx_buffer: self.rx_buffer.clone(),
 
-            topic_rx: self.topic_rx.clone(),
-            topic_tx: self.topic_tx.clone(),
+            topics: self.topics.clone(),
         }
     }
 }
@@ -44,55 +115,122 @@ impl SerialPortClient {
         SerialPortClientBuilder::default()
     }
 
+    /// Discover the instances served through the given broker
+    ///
+    /// Collects the registry entries retained by every server sharing the
+    /// broker, sorted by name. Fails if none is received within `timeout`
+    /// (no server connected to this broker).
+    pub async fn discover(
+        broker: impl Into<BrokerEndpoint>,
+        timeout: Duration,
+    ) -> anyhow::Result<Vec<InstanceInfo>> {
+        let options = SerialPortClientBuilder::default()
+            .with_ip(broker)
+            .mqtt_options();
+        let (client, mut event_loop) = AsyncClient::new(options, 10);
+        client
+            .subscribe(Topics::all_instance_entries(), rumqttc::QoS::AtMostOnce)
+            .await?;
+
+        // The entries are retained, they are all sent right after the
+        // subscription: stop once none arrived for a short while
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut instances = BTreeMap::new();
+        let mut wait_until = deadline;
+        while let Ok(event) = tokio::time::timeout_at(wait_until, event_loop.poll()).await {
+            if let rumqttc::Event::Incoming(rumqttc::Packet::Publish(packet)) = event? {
+                // An empty retained message clears the entry, an invalid one
+                // does not hide the other instances
+                if !packet.payload.is_empty() {
+                    match InstanceInfo::from_json_bytes(packet.payload) {
+                        Ok(info) => {
+                            instances.insert(info.name.clone(), info);
+                        }
+                        Err(e) => tracing::warn!(
+                            "Skipping invalid registry entry '{}': {}",
+                            packet.topic,
+                            e
+                        ),
+                    }
+                }
+                wait_until = deadline.min(tokio::time::Instant::now() + DISCOVER_SETTLE_DELAY);
+            }
+        }
+        let _ = client.try_disconnect();
+
+        if instances.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No instance registry received within {:?}",
+                timeout
+            ));
+        }
+        Ok(instances.into_values().collect())
+    }
+
+    // ------------------------------------------------------------------------
+
     /// Task loop to handle MQTT events and update client state
+    ///
+    /// The event loop reconnects by itself on the next poll, this loop only
+    /// paces the attempts and resubscribes once the broker accepts the
+    /// connection (subscriptions do not survive a clean session).
     async fn task_loop(
         client: SerialPortClient,
         mut event_loop: rumqttc::EventLoop,
         sub_topics: Vec<String>,
     ) {
-        // Subscribe to all relevant topics
-        client
-            .mqtt_client
-            .subscribe_to_all(sub_topics.clone())
-            .await;
-
+        let mut attempt = 0u32;
+        let mut awaiting_suback = false;
         loop {
-            while let Ok(event) = event_loop.poll().await {
-                // println!("Notification = {:?}", event);
-                // match notification {
-                //     Ok(event) => {
-                match event {
-                    rumqttc::Event::Incoming(incoming) => {
-                        // println!("Incoming = {:?}", incoming);
-
-                        match incoming {
-                            // rumqttc::Packet::Connect(_) => todo!(),
-                            // rumqttc::Packet::ConnAck(_) => todo!(),
-                            rumqttc::Packet::Publish(packet) => {
-                                // println!("Publish = {:?}", packet);
-                                let topic = packet.topic;
-                                let payload = packet.payload;
-
-                                client
-                                    .handle_incoming_message(&topic, payload)
-                                    .await
-                                    .expect("error handling incoming message    ");
-                            }
-
-                            _ => {}
+            match event_loop.poll().await {
+                Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                    attempt = 0;
+                    awaiting_suback = true;
+
+                    // Requests are sent from a task, the event loop must keep polling
+                    let client = client.clone();
+                    let filters = sub_topics
+                        .iter()
+                        .map(|topic| {
+                            rumqttc::SubscribeFilter::new(topic.clone(), rumqttc::QoS::AtMostOnce)
+                        })
+                        .collect::<Vec<_>>();
+                    tokio::spawn(async move {
+                        if let Err(e) = client.mqtt_client.subscribe_many(filters).await {
+                            client.report_error(format!("Failed to subscribe: {}", e));
                         }
-                    }
-                    rumqttc::Event::Outgoing(outgoing) => {
-                        // println!("Outgoing = {:?}", outgoing);
-                        match outgoing {
-                            // rumqttc::Outgoing::Publish(packet) => {
-                            //     // println!("Publish = {:?}", packet);
-                            // }
-                            _ => {}
+                        if let Err(e) = client.publish_presence(Presence::Online).await {
+                            client.report_error(format!("Failed to publish presence: {}", e));
                         }
-                    } // }
-                      // }
-                      // Err(_) => todo!(),
+                    });
+                }
+                Ok(rumqttc::Event::Incoming(rumqttc::Packet::SubAck(_))) if awaiting_suback => {
+                    awaiting_suback = false;
+                    client
+                        .connection_channel
+                        .send_replace(ConnectionState::Connected);
+                }
+                Ok(rumqttc::Event::Incoming(rumqttc::Packet::Publish(packet))) => {
+                    if let Err(e) = client
+                        .handle_incoming_message(&packet.topic, packet.payload)
+                        .await
+                    {
+                        client
+                            .report_error(format!("Invalid message on '{}': {}", packet.topic, e));
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    attempt = attempt.saturating_add(1);
+                    let retry_in = connection::backoff(attempt);
+                    client
+                        .connection_channel
+                        .send_replace(ConnectionState::Reconnecting {
+                            error: e.to_string(),
+                            attempt,
+                            retry_in,
+                        });
+                    tokio::time::sleep(retry_in).await;
                 }
             }
         }
@@ -100,12 +238,42 @@ impl SerialPortClient {
 
     // ------------------------------------------------------------------------
 
+    /// Report an error of the client itself on the error channel
+    fn report_error(&self, message: String) {
+        let _ = self.error_channel.send(ErrorPayload::from_message(message));
+    }
+
+    // ------------------------------------------------------------------------
+
     /// Handle incoming MQTT messages and update internal state
     async fn handle_incoming_message(&self, topic: &String, payload: Bytes) -> anyhow::Result<()> {
-        if topic == &self.topic_rx {
-            self.rx_channel.0.send(payload)?;
-        } else if topic == &self.topic_tx {
-            self.tx_channel.0.send(payload)?;
+        if topic == &self.topics.rx {
+            self.rx_buffer.push(&payload);
+            // No subscriber is not an error, the data is simply dropped
+            let _ = self.rx_channel.0.send(payload);
+        } else if topic == &self.topics.tx {
+            let _ = self.tx_channel.0.send(payload);
+        } else if topic == &self.topics.rx_chunks {
+            let chunk = BytesPayload::from_json_bytes(payload)?;
+            let _ = self.rx_chunks_channel.send(chunk);
+        } else if topic == &self.topics.tx_ack {
+            let ack = TxAckPayload::from_json_bytes(payload)?;
+            let _ = self.tx_ack_channel.send(ack);
+        } else if topic == &self.topics.error {
+            let error = ErrorPayload::from_json_bytes(payload)?;
+            let _ = self.error_channel.send(error);
+        } else if topic == &self.topics.settings {
+            let settings = LineSettingsPayload::from_json_bytes(payload)?;
+            let _ = self.settings_channel.send(settings);
+        } else if topic == &self.topics.stats {
+            let stats = StatsPayload::from_json_bytes(payload)?;
+            self.stats_channel.send_replace(Some(stats));
+        } else if topic == &self.topics.status {
+            let status = StatusPayload::from_json_bytes(payload)?;
+            self.status_channel.send_replace(Some(status));
+        } else if topic == &self.topics.presence {
+            let presence = Presence::from_bytes(&payload)?;
+            self.presence_channel.send_replace(Some(presence));
         }
         Ok(())
     }
@@ -116,38 +284,63 @@ impl SerialPortClient {
     pub fn new_with_client(
         psu_name: String,
         client: AsyncClient,
-        event_loop: rumqttc::EventLoop,
+        mut event_loop: rumqttc::EventLoop,
         enable_tx_monitoring: bool,
+        enable_rx_chunks: bool,
+        channel_capacity: usize,
     ) -> Self {
-        let cccc = RumqttCustomAsyncClient::new(
-            client,
-            rumqttc::QoS::AtMostOnce,
-            true,
-            format!(
-                "{}/{}",
-                crate::constants::SERVER_TYPE_NAME,
-                psu_name.clone()
-            ),
-        );
-
-        let (channel_tx, channel_rx) = broadcast::channel(32);
-        let (tx_channel_tx, tx_channel_rx) = broadcast::channel(32);
+        let topics = Topics::new(&psu_name);
+
+        // The broker reports this client offline if the connection is lost.
+        // Not retained: client ids are random, retained presences would pile
+        // up on the broker.
+        let client_id = event_loop.mqtt_options.client_id();
+        event_loop
+            .mqtt_options
+            .set_last_will(rumqttc::LastWill::new(
+                topics.client_presence(&client_id),
+                Presence::Offline.to_bytes().to_vec(),
+                rumqttc::QoS::AtLeastOnce,
+                false,
+            ));
+
+        let (channel_tx, channel_rx) = broadcast::channel(channel_capacity);
+        let (tx_channel_tx, tx_channel_rx) = broadcast::channel(channel_capacity);
+        let (rx_chunks_channel_tx, _) = broadcast::channel(channel_capacity);
+        let (tx_ack_channel_tx, _) = broadcast::channel(channel_capacity);
+        let (error_channel_tx, _) = broadcast::channel(channel_capacity);
+        let (settings_channel_tx, _) = broadcast::channel(channel_capacity);
+        let (stats_channel_tx, _) = watch::channel(None);
+        let (status_channel_tx, _) = watch::channel(None);
+        let (presence_channel_tx, _) = watch::channel(None);
+        let (connection_channel_tx, _) = watch::channel(ConnectionState::Connecting);
 
         let obj = Self {
+            topics,
             instance_name: psu_name,
-            topic_rx: cccc.topic_with_prefix("rx"),
-            topic_tx: cccc.topic_with_prefix("tx"),
-            mqtt_client: cccc,
+            client_id,
+            mqtt_client: client,
 
             rx_channel: (channel_tx, channel_rx),
             tx_channel: (tx_channel_tx, tx_channel_rx),
+            rx_chunks_channel: rx_chunks_channel_tx,
+            tx_ack_channel: tx_ack_channel_tx,
+            error_channel: error_channel_tx,
+            settings_channel: settings_channel_tx,
+            stats_channel: Arc::new(stats_channel_tx),
+            status_channel: Arc::new(status_channel_tx),
+            presence_channel: Arc::new(presence_channel_tx),
+            connection_channel: Arc::new(connection_channel_tx),
+            rx_buffer: Arc::new(expect::RxBuffer::new()),
         };
 
-        let sub_topics = if enable_tx_monitoring {
-            vec![obj.topic_rx.clone(), obj.topic_tx.clone()]
-        } else {
-            vec![obj.topic_rx.clone()]
-        };
+        let mut sub_topics = obj.topics.vec_sub_client();
+        if enable_tx_monitoring {
+            sub_topics.push(obj.topics.tx.clone());
+        }
+        if enable_rx_chunks {
+            sub_topics.push(obj.topics.rx_chunks.clone());
+        }
 
         let _task_handler = tokio::spawn(Self::task_loop(obj.clone(), event_loop, sub_topics));
         obj
@@ -155,23 +348,229 @@ impl SerialPortClient {
 
     // ------------------------------------------------------------------------
 
+    /// Topics used by this client, as published by the server
+    pub fn topics(&self) -> &Topics {
+        &self.topics
+    }
+
+    // ------------------------------------------------------------------------
+
     /// Subscribe to output current state changes
     pub fn subscribe_rx(&self) -> broadcast::Receiver<Bytes> {
         self.rx_channel.0.subscribe()
     }
 
+    /// Stream of the received data, losses are reported as `RxEvent::Gap`
+    pub fn rx_stream(&self) -> RxStream {
+        RxStream::new(self.subscribe_rx())
+    }
+
+    /// Get an `AsyncRead + AsyncWrite` stream over the port
+    pub fn stream(&self) -> SerialPortStream {
+        SerialPortStream::new(self.clone())
+    }
+
     pub fn subscribe_tx(&self) -> broadcast::Receiver<Bytes> {
         self.tx_channel.0.subscribe()
     }
 
+    /// Subscribe to structured rx chunks
+    ///
+    /// Requires `enable_rx_chunks` on the builder and `structured_rx`
+    /// on the server instance.
+    pub fn subscribe_rx_chunks(&self) -> broadcast::Receiver<BytesPayload> {
+        self.rx_chunks_channel.subscribe()
+    }
+
+    /// Subscribe to errors reported by the server
+    pub fn subscribe_errors(&self) -> broadcast::Receiver<ErrorPayload> {
+        self.error_channel.subscribe()
+    }
+
+    /// Subscribe to the port statistics (None until the first publication)
+    pub fn subscribe_stats(&self) -> watch::Receiver<Option<StatsPayload>> {
+        self.stats_channel.subscribe()
+    }
+
+    /// Get the latest port statistics received from the server
+    pub fn stats(&self) -> Option<StatsPayload> {
+        self.stats_channel.borrow().clone()
+    }
+
+    /// Subscribe to the lifecycle status (None until the first publication)
+    pub fn subscribe_status(&self) -> watch::Receiver<Option<StatusPayload>> {
+        self.status_channel.subscribe()
+    }
+
+    /// Get the latest lifecycle status received from the server
+    pub fn status(&self) -> Option<StatusPayload> {
+        self.status_channel.borrow().clone()
+    }
+
+    /// Subscribe to the state of the connection to the broker
+    pub fn subscribe_connection(&self) -> watch::Receiver<ConnectionState> {
+        self.connection_channel.subscribe()
+    }
+
+    /// Get the current state of the connection to the broker
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection_channel.borrow().clone()
+    }
+
+    /// Wait until the client is connected and subscribed
+    ///
+    /// Data published by the server before this point is not received,
+    /// so call it before the first `send` expecting an answer.
+    pub async fn connected(&self) -> anyhow::Result<()> {
+        let mut connection = self.subscribe_connection();
+        connection
+            .wait_for(|state| *state == ConnectionState::Connected)
+            .await?;
+        Ok(())
+    }
+
+    /// Subscribe to the presence of the instance runner (None until received)
+    ///
+    /// `Offline` is published by the broker when the runner connection is lost,
+    /// which tells a crashed server apart from a quiet device.
+    pub fn subscribe_presence(&self) -> watch::Receiver<Option<Presence>> {
+        self.presence_channel.subscribe()
+    }
+
+    /// Get the latest presence of the instance runner
+    pub fn presence(&self) -> Option<Presence> {
+        *self.presence_channel.borrow()
+    }
+
+    /// Identifier of this client, its presence is published under
+    /// `Topics::client_presence`
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Wait until the instance reports `Running`
+    ///
+    /// Transient states (initializing, reconnecting...) are waited through,
+    /// `Stopped` fails immediately since the instance will not come back.
+    pub async fn wait_until_running(&self, timeout: Duration) -> anyhow::Result<()> {
+        let mut status = self.subscribe_status();
+        let wait_running = async {
+            loop {
+                if let Some(current) = status.borrow_and_update().as_ref() {
+                    match current.status {
+                        Status::Running => return Ok::<(), anyhow::Error>(()),
+                        Status::Stopped => {
+                            return Err(anyhow::anyhow!(
+                                "Instance '{}' is stopped: {}",
+                                self.instance_name,
+                                current.message.clone().unwrap_or_default()
+                            ))
+                        }
+                        _ => {}
+                    }
+                }
+                status.changed().await?;
+            }
+        };
+
+        tokio::time::timeout(timeout, wait_running)
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "Instance '{}' not running within {:?}",
+                    self.instance_name,
+                    timeout
+                )
+            })?
+    }
+
     // ------------------------------------------------------------------------
 
-    pub async fn send(&self, bytes: Bytes) -> anyhow::Result<()> {
+    /// Publish a command of this client
+    ///
+    /// Commands are never retained: a retained command would be delivered
+    /// again to a runner that subscribes after a restart.
+    async fn publish(&self, topic: String, payload: Vec<u8>) -> anyhow::Result<()> {
+        self.mqtt_client
+            .publish(topic, rumqttc::QoS::AtMostOnce, false, payload)
+            .await?;
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Publish the presence of this client (not retained, like its last-will)
+    async fn publish_presence(&self, presence: Presence) -> anyhow::Result<()> {
         self.mqtt_client
-            .publish(self.mqtt_client.topic_with_prefix("tx"), bytes.to_vec())
+            .publish(
+                self.topics.client_presence(&self.client_id),
+                rumqttc::QoS::AtMostOnce,
+                false,
+                presence.to_bytes().to_vec(),
+            )
             .await?;
         Ok(())
     }
 
     // ------------------------------------------------------------------------
+
+    pub async fn send(&self, bytes: Bytes) -> anyhow::Result<()> {
+        self.publish(self.topics.tx.clone(), bytes.to_vec()).await?;
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Send bytes and wait until the server reports them written to the wire
+    ///
+    /// Fails with the server error message if the write failed, or if no
+    /// answer is received within `timeout`.
+    pub async fn send_confirmed(&self, bytes: Bytes, timeout: Duration) -> anyhow::Result<()> {
+        let command = BytesPayload::from_data(bytes);
+
+        // Subscribe before publishing to be sure not to miss the answer
+        let mut acks = self.tx_ack_channel.subscribe();
+        let mut errors = self.error_channel.subscribe();
+
+        self.publish(
+            self.topics.tx_confirmed.clone(),
+            command.to_json_bytes()?.to_vec(),
+        )
+        .await?;
+
+        let wait_answer = async {
+            loop {
+                tokio::select! {
+                    ack = acks.recv() => match ack {
+                        Ok(ack) if ack.pza_id == command.pza_id => return Ok(()),
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(anyhow::anyhow!("Client acknowledgement channel closed"))
+                        }
+                    },
+                    error = errors.recv() => match error {
+                        Ok(error) if error.pza_id == command.pza_id => {
+                            return Err(anyhow::anyhow!(error.message))
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(anyhow::anyhow!("Client error channel closed"))
+                        }
+                    },
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, wait_answer)
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "No acknowledgement received for tx command '{}' within {:?}",
+                    command.pza_id,
+                    timeout
+                )
+            })?
+    }
+
+    // ------------------------------------------------------------------------
 }
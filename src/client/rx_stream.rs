@@ -0,0 +1,82 @@
+use bytes::Bytes;
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Item of an `RxStream`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RxEvent {
+    /// Data received from the serial port
+    Data(Bytes),
+    /// The consumer was too slow, `dropped` chunks have been lost
+    Gap { dropped: u64 },
+}
+
+/// Pending read on the rx channel, gives the receiver back once done
+type RecvFuture =
+    Pin<Box<dyn Future<Output = (Result<Bytes, RecvError>, broadcast::Receiver<Bytes>)> + Send>>;
+
+/// Stream of the data received from the serial port
+///
+/// Unlike a raw broadcast receiver, losses are reported explicitly with
+/// a `Gap` event. The stream ends if the rx channel is closed.
+pub struct RxStream {
+    /// Receiver of the rx data, None while a read is pending or once closed
+    rx: Option<broadcast::Receiver<Bytes>>,
+    /// Pending read
+    recv: Option<RecvFuture>,
+}
+
+impl RxStream {
+    /// Create a stream, only data received from now on is yielded
+    pub fn new(rx: broadcast::Receiver<Bytes>) -> Self {
+        Self {
+            rx: Some(rx),
+            recv: None,
+        }
+    }
+}
+
+impl Stream for RxStream {
+    type Item = RxEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let mut recv: RecvFuture = match this.recv.take() {
+            Some(recv) => recv,
+            None => {
+                let Some(mut rx) = this.rx.take() else {
+                    return Poll::Ready(None);
+                };
+                Box::pin(async move {
+                    let result = rx.recv().await;
+                    (result, rx)
+                })
+            }
+        };
+
+        let (result, rx) = match recv.as_mut().poll(cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => {
+                this.recv = Some(recv);
+                return Poll::Pending;
+            }
+        };
+        match result {
+            Ok(data) => {
+                this.rx = Some(rx);
+                Poll::Ready(Some(RxEvent::Data(data)))
+            }
+            Err(RecvError::Lagged(dropped)) => {
+                this.rx = Some(rx);
+                Poll::Ready(Some(RxEvent::Gap { dropped }))
+            }
+            Err(RecvError::Closed) => Poll::Ready(None),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::client::SerialPortClient;
+use crate::payload::LineSettingsPayload;
+
+impl SerialPortClient {
+    // ------------------------------------------------------------------------
+
+    /// Read the line settings of the port (baud rate, parity, stop bits...)
+    pub async fn line_settings(&self, timeout: Duration) -> anyhow::Result<LineSettingsPayload> {
+        self.line_settings_command(LineSettingsPayload::request(), timeout)
+            .await
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Change the line settings set in `changes`, the others are kept
+    ///
+    /// Returns every setting actually applied by the driver, which may differ
+    /// from the requested ones.
+    pub async fn set_line_settings(
+        &self,
+        changes: LineSettingsPayload,
+        timeout: Duration,
+    ) -> anyhow::Result<LineSettingsPayload> {
+        self.line_settings_command(changes, timeout).await
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Publish a line settings command and wait for its answer
+    async fn line_settings_command(
+        &self,
+        command: LineSettingsPayload,
+        timeout: Duration,
+    ) -> anyhow::Result<LineSettingsPayload> {
+        // Subscribe before publishing to be sure not to miss the answer
+        let mut answers = self.settings_channel.subscribe();
+        let mut errors = self.error_channel.subscribe();
+
+        self.publish(
+            self.topics.settings_cmd.clone(),
+            command.to_json_bytes()?.to_vec(),
+        )
+        .await?;
+
+        let wait_answer = async {
+            loop {
+                tokio::select! {
+                    answer = answers.recv() => match answer {
+                        Ok(answer) if answer.pza_id == command.pza_id => return Ok(answer),
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(anyhow::anyhow!("Client settings channel closed"))
+                        }
+                    },
+                    error = errors.recv() => match error {
+                        Ok(error) if error.pza_id == command.pza_id => {
+                            return Err(anyhow::anyhow!(error.message))
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(anyhow::anyhow!("Client error channel closed"))
+                        }
+                    },
+                }
+            }
+        };
+
+        tokio::time::timeout(timeout, wait_answer)
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "No answer received for line settings command '{}' within {:?}",
+                    command.pza_id,
+                    timeout
+                )
+            })?
+    }
+
+    // ------------------------------------------------------------------------
+}
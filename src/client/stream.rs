@@ -0,0 +1,129 @@
+use bytes::Buf;
+use bytes::Bytes;
+use futures_core::Stream;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+
+use super::RxEvent;
+use super::RxStream;
+use super::SerialPortClient;
+
+/// Pending publication of written bytes
+type SendFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+/// Byte stream over a remote serial port
+///
+/// Reads come from the rx topic and writes are published on the tx topic,
+/// so codecs written for a local `AsyncRead + AsyncWrite` port work unchanged.
+/// Reading fails with an I/O error if rx data was dropped because the reader
+/// lagged behind, instead of silently skipping it.
+pub struct SerialPortStream {
+    /// Client of the instance
+    client: SerialPortClient,
+    /// Data received from the port, with the losses
+    rx: RxStream,
+    /// Received bytes not consumed by the reader yet
+    pending: Bytes,
+    /// Pending write
+    send: Option<SendFuture>,
+}
+
+impl SerialPortStream {
+    // ------------------------------------------------------------------------
+
+    /// Create a stream, only data received from now on is readable
+    pub fn new(client: SerialPortClient) -> Self {
+        Self {
+            rx: RxStream::new(client.subscribe_rx()),
+            client,
+            pending: Bytes::new(),
+            send: None,
+        }
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Client used by this stream
+    pub fn client(&self) -> &SerialPortClient {
+        &self.client
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Drive the pending write to completion
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(send) = self.send.as_mut() {
+            let result = std::task::ready!(send.as_mut().poll(cx));
+            self.send = None;
+            result.map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    // ------------------------------------------------------------------------
+}
+
+impl AsyncRead for SerialPortStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        while this.pending.is_empty() {
+            match std::task::ready!(Pin::new(&mut this.rx).poll_next(cx)) {
+                Some(RxEvent::Data(data)) => this.pending = data,
+                Some(RxEvent::Gap { dropped }) => {
+                    return Poll::Ready(Err(io::Error::other(format!(
+                        "rx stream lagged, {} chunks lost",
+                        dropped
+                    ))));
+                }
+                // Channel closed, end of stream
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+
+        let len = this.pending.len().min(buf.remaining());
+        buf.put_slice(&this.pending[..len]);
+        this.pending.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for SerialPortStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // Nothing to send, an empty publication would reach the port as a no-op write
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let this = self.get_mut();
+
+        // Keep the writes ordered, one publication at a time
+        std::task::ready!(this.poll_send(cx))?;
+
+        let client = this.client.clone();
+        let data = Bytes::copy_from_slice(buf);
+        this.send = Some(Box::pin(async move { client.send(data).await }));
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send(cx)
+    }
+}
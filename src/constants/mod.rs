@@ -1,3 +1,10 @@
+pub mod topics;
+
+pub use topics::Topics;
+
 pub const SERVER_TYPE_NAME: &str = "serial-port";
 pub const FILE_NAME_PREFIX: &str = "pza";
 pub const DEFAULT_MCP_PORT: u16 = 5002;
+
+/// Name of the MCP endpoint serving every instance, `/serial-port/_all`
+pub const MCP_ALL_PORTS_ENDPOINT: &str = "_all";
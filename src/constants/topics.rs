@@ -1,15 +1,36 @@
+use serde::{Deserialize, Serialize};
+
 use crate::constants::SERVER_TYPE_NAME;
 
+/// Version of the topic schema, bumped on any incompatible layout change
+pub const TOPICS_SCHEMA_VERSION: u32 = 1;
+
+/// Identifier of a fixed topic of an instance, see [`Topics::id_to_topic`]
 pub enum TopicId {
     Status,
     Error,
     Tx,
     Rx,
+    RxChunks,
+    TxConfirmed,
+    TxAck,
+    AutoBaudCmd,
+    AutoBaud,
+    SettingsCmd,
+    Settings,
+    Stats,
+    Schema,
+    Presence,
 }
 
-/// Topics used for MQTT communication with the power supply
+/// Topics used for MQTT communication with a serial port instance
+///
+/// Single source of truth for the topic layout, shared by the server
+/// (runners, drivers) and the client.
 #[derive(Debug, Clone)]
 pub struct Topics {
+    /// Topic prefix of the instance (serial-port/{name})
+    pub prefix: String,
     // ---
     /// Topic for status updates
     pub status: String,
@@ -21,7 +42,30 @@ pub struct Topics {
     pub tx: String,
     /// Topic to receive data from equipment
     pub rx: String,
+    /// Topic to receive data from equipment as timestamped chunks
+    pub rx_chunks: String,
+    // ---
+    /// Topic to send data with an acknowledgement
+    pub tx_confirmed: String,
+    /// Topic for acknowledgements of confirmed tx commands
+    pub tx_ack: String,
+    // ---
+    /// Topic to trigger a baud rate detection
+    pub autobaud_cmd: String,
+    /// Topic for baud rate detection results
+    pub autobaud: String,
     // ---
+    /// Topic to read or change the line settings
+    pub settings_cmd: String,
+    /// Topic for the line settings applied by the driver
+    pub settings: String,
+    // ---
+    /// Topic for traffic and error statistics
+    pub stats: String,
+    /// Topic for the descriptor of this topic layout
+    pub schema: String,
+    /// Topic for the presence of the runner (online/offline, retained)
+    pub presence: String,
 }
 
 impl Topics {
@@ -31,41 +75,359 @@ impl Topics {
         Self {
             status: format!("{}/status", prefix),
             error: format!("{}/error", prefix),
-            tx: format!("{}/state/cmd", prefix),
-            rx: format!("{}/state", prefix),
+            tx: format!("{}/tx", prefix),
+            rx: format!("{}/rx", prefix),
+            rx_chunks: format!("{}/rx/chunks", prefix),
+            tx_confirmed: format!("{}/tx/confirmed", prefix),
+            tx_ack: format!("{}/tx/ack", prefix),
+            autobaud_cmd: format!("{}/autobaud/cmd", prefix),
+            autobaud: format!("{}/autobaud", prefix),
+            settings_cmd: format!("{}/settings/cmd", prefix),
+            settings: format!("{}/settings", prefix),
+            stats: format!("{}/stats", prefix),
+            schema: format!("{}/_schema", prefix),
+            presence: format!("{}/presence", prefix),
+            prefix,
         }
     }
 
+    /// Retained registry entry of an instance
+    ///
+    /// Each instance has its own entry, so servers sharing a broker do not
+    /// overwrite the instances of each other.
+    pub fn instance_entry(name: &str) -> String {
+        format!("{}/_instances/{}", SERVER_TYPE_NAME, name)
+    }
+
+    /// Wildcard matching the registry entries of every instance
+    pub fn all_instance_entries() -> String {
+        format!("{}/_instances/+", SERVER_TYPE_NAME)
+    }
+
+    /// Wildcard matching the status topic of every instance
+    pub fn all_status() -> String {
+        format!("{}/+/status", SERVER_TYPE_NAME)
+    }
+
+    /// Wildcard matching the presence topic of every instance runner
+    pub fn all_presence() -> String {
+        format!("{}/+/presence", SERVER_TYPE_NAME)
+    }
+
+    /// Extract the instance name from the status topic of an instance
+    pub fn instance_of_status(topic: &str) -> Option<&str> {
+        Self::instance_of(topic, "", "/status")
+    }
+
+    /// Extract the instance name from the presence topic of an instance runner
+    pub fn instance_of_presence(topic: &str) -> Option<&str> {
+        Self::instance_of(topic, "", "/presence")
+    }
+
+    /// Extract the instance name from the registry entry of an instance
+    pub fn instance_of_entry(topic: &str) -> Option<&str> {
+        Self::instance_of(topic, "_instances/", "")
+    }
+
+    /// Extract the name found between `prefix` and `suffix` in a topic of the server type
+    fn instance_of<'a>(topic: &'a str, prefix: &str, suffix: &str) -> Option<&'a str> {
+        topic
+            .strip_prefix(SERVER_TYPE_NAME)?
+            .strip_prefix('/')?
+            .strip_prefix(prefix)?
+            .strip_suffix(suffix)
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+    }
+
+    /// Topic for the presence of a client connected to this instance
+    pub fn client_presence(&self, client_id: &str) -> String {
+        format!("{}/_clients/{}/presence", self.prefix, client_id)
+    }
+
     /// Get a vector of all client subscription topics
     pub fn vec_sub_client(&self) -> Vec<String> {
-        vec![self.status.clone(), self.error.clone(), self.rx.clone()]
+        vec![
+            self.status.clone(),
+            self.error.clone(),
+            self.rx.clone(),
+            self.tx_ack.clone(),
+            self.autobaud.clone(),
+            self.settings.clone(),
+            self.stats.clone(),
+            self.presence.clone(),
+        ]
     }
 
     /// Get a vector of all server subscription topics
     pub fn vec_sub_server(&self) -> Vec<String> {
-        vec![self.tx.clone()]
+        vec![
+            self.tx.clone(),
+            self.tx_confirmed.clone(),
+            self.autobaud_cmd.clone(),
+            self.settings_cmd.clone(),
+        ]
     }
 
+    /// Identify a fixed topic of this instance, `None` for any other topic
     pub fn topic_to_id(&self, topic: &str) -> Option<TopicId> {
-        if topic == self.status {
-            Some(TopicId::Status)
-        } else if topic == self.error {
-            Some(TopicId::Error)
-        } else if topic == self.tx {
-            Some(TopicId::Tx)
-        } else if topic == self.rx {
-            Some(TopicId::Rx)
-        } else {
-            None
-        }
+        [
+            TopicId::Status,
+            TopicId::Error,
+            TopicId::Tx,
+            TopicId::Rx,
+            TopicId::RxChunks,
+            TopicId::TxConfirmed,
+            TopicId::TxAck,
+            TopicId::AutoBaudCmd,
+            TopicId::AutoBaud,
+            TopicId::SettingsCmd,
+            TopicId::Settings,
+            TopicId::Stats,
+            TopicId::Schema,
+            TopicId::Presence,
+        ]
+        .into_iter()
+        .find(|id| self.id_to_topic(id) == topic)
     }
 
+    /// Full topic name of the given identifier
     pub fn id_to_topic(&self, id: &TopicId) -> &str {
         match id {
             TopicId::Status => &self.status,
             TopicId::Error => &self.error,
             TopicId::Tx => &self.tx,
             TopicId::Rx => &self.rx,
+            TopicId::RxChunks => &self.rx_chunks,
+            TopicId::TxConfirmed => &self.tx_confirmed,
+            TopicId::TxAck => &self.tx_ack,
+            TopicId::AutoBaudCmd => &self.autobaud_cmd,
+            TopicId::AutoBaud => &self.autobaud,
+            TopicId::SettingsCmd => &self.settings_cmd,
+            TopicId::Settings => &self.settings,
+            TopicId::Stats => &self.stats,
+            TopicId::Schema => &self.schema,
+            TopicId::Presence => &self.presence,
+        }
+    }
+
+    /// Build the descriptor of this topic layout, published on `schema`
+    pub fn descriptor(&self) -> TopicsDescriptor {
+        use TopicDirection::*;
+        let entry =
+            |topic: &String, direction, encoding: &str, description: &str| TopicDescriptor {
+                topic: topic.clone(),
+                direction,
+                encoding: encoding.to_string(),
+                description: description.to_string(),
+            };
+
+        TopicsDescriptor {
+            schema_version: TOPICS_SCHEMA_VERSION,
+            prefix: self.prefix.clone(),
+            topics: vec![
+                entry(
+                    &self.status,
+                    ServerToClient,
+                    "json:StatusPayload",
+                    "Lifecycle status (retained)",
+                ),
+                entry(
+                    &self.error,
+                    ServerToClient,
+                    "json:ErrorPayload",
+                    "Errors, pza_id echoes the failed command",
+                ),
+                entry(
+                    &self.tx,
+                    ClientToServer,
+                    "raw",
+                    "Bytes to write to the serial port",
+                ),
+                entry(
+                    &self.rx,
+                    ServerToClient,
+                    "raw",
+                    "Bytes read from the serial port",
+                ),
+                entry(
+                    &self.rx_chunks,
+                    ServerToClient,
+                    "json:BytesPayload",
+                    "Read bytes with timestamp and sequence number (optional)",
+                ),
+                entry(
+                    &self.tx_confirmed,
+                    ClientToServer,
+                    "json:BytesPayload",
+                    "Bytes to write, acknowledged on tx/ack",
+                ),
+                entry(
+                    &self.tx_ack,
+                    ServerToClient,
+                    "json:TxAckPayload",
+                    "Acknowledgement of a confirmed tx command",
+                ),
+                entry(
+                    &self.autobaud_cmd,
+                    ClientToServer,
+                    "json:AutoBaudPayload",
+                    "Trigger a baud rate detection",
+                ),
+                entry(
+                    &self.autobaud,
+                    ServerToClient,
+                    "json:AutoBaudPayload",
+                    "Baud rate detection result",
+                ),
+                entry(
+                    &self.settings_cmd,
+                    ClientToServer,
+                    "json:LineSettingsPayload",
+                    "Read the line settings, or change the given ones",
+                ),
+                entry(
+                    &self.settings,
+                    ServerToClient,
+                    "json:LineSettingsPayload",
+                    "Line settings applied by the driver (retained)",
+                ),
+                entry(
+                    &self.stats,
+                    ServerToClient,
+                    "json:StatsPayload",
+                    "Traffic and line error statistics (retained)",
+                ),
+                entry(
+                    &self.presence,
+                    ServerToClient,
+                    "text:online|offline",
+                    "Presence of the runner, offline is its last-will (retained)",
+                ),
+                entry(
+                    &self.client_presence("{client_id}"),
+                    ClientToServer,
+                    "text:online|offline",
+                    "Presence of a client, one topic per client (not retained)",
+                ),
+                entry(
+                    &self.schema,
+                    ServerToClient,
+                    "json:TopicsDescriptor",
+                    "This descriptor (retained)",
+                ),
+            ],
         }
     }
 }
+
+/// Direction of the data flowing on a topic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopicDirection {
+    /// Published by the server, consumed by clients
+    ServerToClient,
+    /// Published by clients, consumed by the server
+    ClientToServer,
+}
+
+/// Description of one topic, for tools that do not use the Rust client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicDescriptor {
+    /// Full topic name
+    pub topic: String,
+    /// Direction of the data
+    pub direction: TopicDirection,
+    /// Payload encoding, `raw` bytes or `json:<PayloadType>`
+    pub encoding: String,
+    /// Human readable description
+    pub description: String,
+}
+
+/// Description of the topic layout of an instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicsDescriptor {
+    /// Version of the topic schema
+    pub schema_version: u32,
+    /// Topic prefix of the instance
+    pub prefix: String,
+    /// Every topic of the instance
+    pub topics: Vec<TopicDescriptor>,
+}
+
+impl TopicsDescriptor {
+    /// Serialize the TopicsDescriptor to JSON bytes
+    pub fn to_json_bytes(&self) -> anyhow::Result<bytes::Bytes> {
+        Ok(bytes::Bytes::from(serde_json::to_string(self)?))
+    }
+
+    /// Deserialize a TopicsDescriptor from JSON bytes
+    pub fn from_json_bytes(bytes: bytes::Bytes) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_of_status_extracts_the_name() {
+        let topics = Topics::new("dut");
+        assert_eq!(Topics::instance_of_status(&topics.status), Some("dut"));
+    }
+
+    #[test]
+    fn instance_of_status_rejects_other_topics() {
+        let topics = Topics::new("dut");
+        assert_eq!(Topics::instance_of_status(&topics.rx), None);
+        assert_eq!(Topics::instance_of_status("other/dut/status"), None);
+        assert_eq!(Topics::instance_of_status("serial-port//status"), None);
+        assert_eq!(Topics::instance_of_status("serial-port/a/b/status"), None);
+        assert_eq!(Topics::instance_of_status("serial-port/dut/status/x"), None);
+    }
+
+    #[test]
+    fn instance_of_presence_and_entry_extract_the_name() {
+        let topics = Topics::new("dut");
+        assert_eq!(Topics::instance_of_presence(&topics.presence), Some("dut"));
+        assert_eq!(
+            Topics::instance_of_presence(&topics.client_presence("c1")),
+            None
+        );
+        assert_eq!(
+            Topics::instance_of_entry(&Topics::instance_entry("dut")),
+            Some("dut")
+        );
+        assert_eq!(Topics::instance_of_entry("serial-port/_instances/"), None);
+        assert_eq!(Topics::instance_of_entry(&topics.status), None);
+    }
+
+    #[test]
+    fn topic_to_id_round_trips() {
+        let topics = Topics::new("dut");
+        for topic in topics
+            .vec_sub_client()
+            .iter()
+            .chain(&topics.vec_sub_server())
+        {
+            let id = topics.topic_to_id(topic).expect("known topic");
+            assert_eq!(topics.id_to_topic(&id), topic);
+        }
+        assert!(matches!(
+            topics.topic_to_id(&topics.rx_chunks),
+            Some(TopicId::RxChunks)
+        ));
+        assert!(matches!(
+            topics.topic_to_id(&topics.schema),
+            Some(TopicId::Schema)
+        ));
+    }
+
+    #[test]
+    fn topic_to_id_rejects_unknown_topics() {
+        let topics = Topics::new("dut");
+        assert!(topics.topic_to_id("serial-port/dut/unknown").is_none());
+        assert!(topics.topic_to_id(&Topics::new("other").tx).is_none());
+        assert!(topics.topic_to_id(&topics.client_presence("abc")).is_none());
+    }
+}
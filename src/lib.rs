@@ -1,8 +1,24 @@
 mod client;
 pub mod constants;
 pub mod payload;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "test-support")]
+pub mod test_support;
 // pub mod topics;
 
 pub use constants::*;
 
+#[cfg(feature = "blocking")]
+pub use client::BlockingSerialPortClient;
+pub use client::BrokerEndpoint;
+pub use client::ClientTlsConfig;
+pub use client::ConnectionState;
+#[cfg(feature = "regex")]
+pub use client::ExpectMatch;
+pub use client::RxEvent;
+pub use client::RxStream;
 pub use client::SerialPortClient;
+pub use client::SerialPortClientBuilder;
+pub use client::SerialPortClientBuilderError;
+pub use client::SerialPortStream;
@@ -1,5 +1,3 @@
-mod server;
-
 #[tokio::main]
 async fn main() {
     // Ensure user root directory exists
@@ -10,5 +8,5 @@ async fn main() {
     pza_toolkit::manifest::update_manifest("pza-serial-port");
 
     // Run the power supply server
-    server::run_server().await;
+    pza_serial_port_client::server::run_server().await;
 }
@@ -0,0 +1,32 @@
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Acknowledgement of a confirmed tx command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxAckPayload {
+    /// PZA identifier
+    /// The server echoes the ID of the tx command
+    pub pza_id: String,
+    /// Number of bytes written to the serial port
+    pub bytes_written: usize,
+}
+
+impl TxAckPayload {
+    /// Create a new acknowledgement for the tx command with the given pza_id
+    pub fn new(pza_id: String, bytes_written: usize) -> Self {
+        Self {
+            pza_id,
+            bytes_written,
+        }
+    }
+
+    /// Serialize the TxAckPayload to JSON bytes
+    pub fn to_json_bytes(&self) -> anyhow::Result<Bytes> {
+        Ok(Bytes::from(serde_json::to_string(self)?))
+    }
+
+    /// Deserialize a TxAckPayload from JSON bytes
+    pub fn from_json_bytes(bytes: Bytes) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
@@ -0,0 +1,134 @@
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Score obtained by one candidate baud rate during automatic detection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaudRateScore {
+    /// Candidate baud rate
+    pub baud_rate: u32,
+    /// Number of bytes received at this rate
+    pub bytes: usize,
+    /// Number of printable characters received
+    pub printable: usize,
+    /// Number of framing errors observed (or bytes that look like ones)
+    pub framing_errors: usize,
+    /// Final score, the higher the better
+    pub score: f64,
+}
+
+/// Auto-baud payload
+///
+/// Sent by the client (only `pza_id`) to trigger a detection,
+/// published by the server with the selected rate and the scores.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoBaudPayload {
+    /// PZA identifier
+    /// On the command, the client generates this ID
+    /// On the response, the server echoes this ID
+    pub pza_id: String,
+    /// Selected baud rate, None if no candidate received usable data
+    #[serde(default)]
+    pub baud_rate: Option<u32>,
+    /// Scores of every candidate rate, in the order they were tried
+    #[serde(default)]
+    pub scores: Vec<BaudRateScore>,
+}
+
+impl AutoBaudPayload {
+    /// Create a new detection request
+    pub fn request() -> Self {
+        Self {
+            pza_id: super::generate_pza_id(),
+            baud_rate: None,
+            scores: Vec::new(),
+        }
+    }
+
+    /// Create a detection result from candidate scores, selecting the best one
+    pub fn from_scores(scores: Vec<BaudRateScore>) -> Self {
+        let baud_rate = scores
+            .iter()
+            .filter(|s| s.score > 0.0)
+            .max_by(|a, b| {
+                a.score
+                    .partial_cmp(&b.score)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then(a.bytes.cmp(&b.bytes))
+            })
+            .map(|s| s.baud_rate);
+        Self {
+            pza_id: super::generate_pza_id(),
+            baud_rate,
+            scores,
+        }
+    }
+
+    /// Set the pza_id to answer a given command
+    pub fn with_pza_id(mut self, pza_id: String) -> Self {
+        self.pza_id = pza_id;
+        self
+    }
+
+    /// Serialize the AutoBaudPayload to JSON bytes
+    pub fn to_json_bytes(&self) -> anyhow::Result<Bytes> {
+        Ok(Bytes::from(serde_json::to_string(self)?))
+    }
+
+    /// Deserialize an AutoBaudPayload from JSON bytes
+    pub fn from_json_bytes(bytes: Bytes) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(baud_rate: u32, bytes: usize, score: f64) -> BaudRateScore {
+        BaudRateScore {
+            baud_rate,
+            bytes,
+            printable: 0,
+            framing_errors: 0,
+            score,
+        }
+    }
+
+    #[test]
+    fn best_score_is_selected() {
+        let result = AutoBaudPayload::from_scores(vec![
+            score(9600, 10, 0.2),
+            score(115200, 10, 0.9),
+            score(57600, 10, 0.5),
+        ]);
+        assert_eq!(result.baud_rate, Some(115200));
+        assert_eq!(result.scores.len(), 3);
+    }
+
+    #[test]
+    fn more_bytes_win_a_tie() {
+        let result =
+            AutoBaudPayload::from_scores(vec![score(9600, 5, 1.0), score(115200, 50, 1.0)]);
+        assert_eq!(result.baud_rate, Some(115200));
+    }
+
+    #[test]
+    fn no_positive_score_selects_nothing() {
+        let result =
+            AutoBaudPayload::from_scores(vec![score(9600, 0, 0.0), score(115200, 10, -0.5)]);
+        assert_eq!(result.baud_rate, None);
+    }
+
+    #[test]
+    fn no_candidate_selects_nothing() {
+        assert_eq!(AutoBaudPayload::from_scores(Vec::new()).baud_rate, None);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let payload = AutoBaudPayload::from_scores(vec![score(9600, 10, 0.5)]);
+        let decoded = AutoBaudPayload::from_json_bytes(payload.to_json_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.pza_id, payload.pza_id);
+        assert_eq!(decoded.baud_rate, Some(9600));
+    }
+}
@@ -1,16 +1,45 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use bytes::Bytes;
-use serde::{Deserialize, Serialize};
-use serde_with::{base64::Base64, serde_as};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-/// Error payload for communicating error messages
-#[serde_as]
+/// Bytes payload for carrying serial data with optional metadata
+///
+/// Used as-is for structured rx chunks, where the server fills the
+/// capture timestamp, the sequence number and the instance name.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BytesPayload {
     /// PZA identifier
     pub pza_id: String,
     /// Data
-    #[serde_as(as = "Base64")]
+    #[serde(
+        serialize_with = "serialize_base64",
+        deserialize_with = "deserialize_base64"
+    )]
     pub data: Bytes,
+    /// Capture time in microseconds since the UNIX epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_us: Option<u64>,
+    /// Sequence number, incremented for each chunk of an instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    /// Name of the instance that produced the data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+}
+
+/// Serialize the data as a base64 string
+fn serialize_base64<S: Serializer>(data: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(data))
+}
+
+/// Deserialize the data from a base64 string
+fn deserialize_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    STANDARD
+        .decode(encoded)
+        .map(Bytes::from)
+        .map_err(serde::de::Error::custom)
 }
 
 impl BytesPayload {
@@ -18,9 +47,30 @@ impl BytesPayload {
         Self {
             pza_id: super::generate_pza_id(),
             data,
+            timestamp_us: None,
+            sequence: None,
+            instance: None,
         }
     }
 
+    /// Set the capture timestamp (microseconds since the UNIX epoch)
+    pub fn with_timestamp_us(mut self, timestamp_us: u64) -> Self {
+        self.timestamp_us = Some(timestamp_us);
+        self
+    }
+
+    /// Set the sequence number
+    pub fn with_sequence(mut self, sequence: u64) -> Self {
+        self.sequence = Some(sequence);
+        self
+    }
+
+    /// Set the instance name
+    pub fn with_instance<A: Into<String>>(mut self, instance: A) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
     /// Serialize the BytesPayload to JSON bytes
     pub fn to_json_bytes(&self) -> anyhow::Result<Bytes> {
         Ok(Bytes::from(serde_json::to_string(self)?))
@@ -0,0 +1,38 @@
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use super::Presence;
+use super::Status;
+
+/// Description of a serial port instance served by a server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceInfo {
+    /// Name of the instance, used to build its topics
+    pub name: String,
+    /// Driver model of the instance
+    pub model: String,
+    /// Optional description of the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Serial port endpoint (port name or USB identifiers)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Last known lifecycle status, None before the first status publication
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+    /// Presence of the runner, None before the runner first connected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence: Option<Presence>,
+}
+
+impl InstanceInfo {
+    /// Serialize the InstanceInfo to JSON bytes, the registry entry of the instance
+    pub fn to_json_bytes(&self) -> anyhow::Result<Bytes> {
+        Ok(Bytes::from(serde_json::to_string(self)?))
+    }
+
+    /// Deserialize an InstanceInfo from JSON bytes
+    pub fn from_json_bytes(bytes: Bytes) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
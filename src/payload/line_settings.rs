@@ -0,0 +1,178 @@
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Parity bit of the serial line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+/// Number of stop bits of the serial line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// Flow control of the serial line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControl {
+    /// No flow control
+    None,
+    /// XON/XOFF characters
+    Software,
+    /// RTS/CTS lines
+    Hardware,
+}
+
+/// Line settings payload
+///
+/// Sent by the client on `settings/cmd` with the settings to change (none to
+/// only read them), published by the server on `settings` with every setting
+/// actually applied by the driver.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LineSettingsPayload {
+    /// PZA identifier
+    /// On the command, the client generates this ID
+    /// On the response, the server echoes this ID
+    pub pza_id: String,
+    /// Baud rate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baud_rate: Option<u32>,
+    /// Number of data bits (5 to 8)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_bits: Option<u8>,
+    /// Parity bit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parity: Option<Parity>,
+    /// Number of stop bits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_bits: Option<StopBits>,
+    /// Flow control
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow_control: Option<FlowControl>,
+}
+
+impl LineSettingsPayload {
+    /// Create a new request, without any change it only reads the settings
+    pub fn request() -> Self {
+        Self {
+            pza_id: super::generate_pza_id(),
+            ..Default::default()
+        }
+    }
+
+    /// Change the baud rate
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = Some(baud_rate);
+        self
+    }
+
+    /// Change the number of data bits
+    pub fn with_data_bits(mut self, data_bits: u8) -> Self {
+        self.data_bits = Some(data_bits);
+        self
+    }
+
+    /// Change the parity
+    pub fn with_parity(mut self, parity: Parity) -> Self {
+        self.parity = Some(parity);
+        self
+    }
+
+    /// Change the number of stop bits
+    pub fn with_stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = Some(stop_bits);
+        self
+    }
+
+    /// Change the flow control
+    pub fn with_flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = Some(flow_control);
+        self
+    }
+
+    /// Set the pza_id to answer a given command
+    pub fn with_pza_id(mut self, pza_id: String) -> Self {
+        self.pza_id = pza_id;
+        self
+    }
+
+    /// True if the payload does not change any setting
+    pub fn is_read_only(&self) -> bool {
+        self.baud_rate.is_none()
+            && self.data_bits.is_none()
+            && self.parity.is_none()
+            && self.stop_bits.is_none()
+            && self.flow_control.is_none()
+    }
+
+    /// Add the settings changed by `changes` to these ones, replacing them
+    pub fn merge(mut self, changes: &LineSettingsPayload) -> Self {
+        self.baud_rate = changes.baud_rate.or(self.baud_rate);
+        self.data_bits = changes.data_bits.or(self.data_bits);
+        self.parity = changes.parity.or(self.parity);
+        self.stop_bits = changes.stop_bits.or(self.stop_bits);
+        self.flow_control = changes.flow_control.or(self.flow_control);
+        self
+    }
+
+    /// Serialize the LineSettingsPayload to JSON bytes
+    pub fn to_json_bytes(&self) -> anyhow::Result<Bytes> {
+        Ok(Bytes::from(serde_json::to_string(self)?))
+    }
+
+    /// Deserialize a LineSettingsPayload from JSON bytes
+    pub fn from_json_bytes(bytes: Bytes) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_is_read_only() {
+        assert!(LineSettingsPayload::request().is_read_only());
+        assert!(!LineSettingsPayload::request()
+            .with_parity(Parity::Odd)
+            .is_read_only());
+    }
+
+    #[test]
+    fn merge_keeps_the_settings_not_changed() {
+        let merged = LineSettingsPayload::request()
+            .with_baud_rate(9600)
+            .with_parity(Parity::Even)
+            .merge(
+                &LineSettingsPayload::request()
+                    .with_baud_rate(115200)
+                    .with_stop_bits(StopBits::Two),
+            );
+        assert_eq!(merged.baud_rate, Some(115200));
+        assert_eq!(merged.parity, Some(Parity::Even));
+        assert_eq!(merged.stop_bits, Some(StopBits::Two));
+        assert_eq!(merged.data_bits, None);
+        assert_eq!(merged.flow_control, None);
+    }
+
+    #[test]
+    fn json_omits_the_settings_not_given() {
+        let request = LineSettingsPayload::request().with_baud_rate(57600);
+        let json = String::from_utf8(request.to_json_bytes().unwrap().to_vec()).unwrap();
+        assert!(json.contains("\"baud_rate\":57600"));
+        assert!(!json.contains("parity"));
+        let decoded =
+            LineSettingsPayload::from_json_bytes(request.to_json_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, request);
+    }
+}
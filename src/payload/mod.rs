@@ -1,8 +1,32 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+mod ack;
+mod baud_rate;
 mod bytes;
 mod error;
+mod instances;
+mod line_settings;
+mod presence;
+mod stats;
 mod status;
 
+pub use self::bytes::BytesPayload;
+pub use ack::TxAckPayload;
+pub use baud_rate::AutoBaudPayload;
+pub use baud_rate::BaudRateScore;
 pub use error::ErrorPayload;
+pub use instances::InstanceInfo;
+pub use line_settings::FlowControl;
+pub use line_settings::LineSettingsPayload;
+pub use line_settings::Parity;
+pub use line_settings::StopBits;
+pub use presence::Presence;
+pub use stats::LineCounters;
+pub use stats::StatsPayload;
 pub use status::Status;
 pub use status::StatusPayload;
 
@@ -11,5 +35,30 @@ pub type PzaId = String;
 
 /// Generate a random 5-character PZA ID
 pub fn generate_pza_id() -> String {
-    pza_toolkit::rand::generate_random_string(5)
+    generate_random_string(5)
+}
+
+/// Characters of the random strings
+const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Generate a random alphanumeric string
+///
+/// Not cryptographic: the std randomly seeded hasher mixes a process wide
+/// counter, enough for unique ids without pulling a random crate in the client.
+pub(crate) fn generate_random_string(len: usize) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut value = 0u64;
+    (0..len)
+        .map(|index| {
+            // A 64-bit value gives 10 characters, take 8 to keep them uniform enough
+            if index % 8 == 0 {
+                let mut hasher = RandomState::new().build_hasher();
+                hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+                value = hasher.finish();
+            }
+            let c = ALPHANUMERIC[(value % ALPHANUMERIC.len() as u64) as usize];
+            value /= ALPHANUMERIC.len() as u64;
+            char::from(c)
+        })
+        .collect()
 }
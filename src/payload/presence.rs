@@ -0,0 +1,42 @@
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Presence of an MQTT connection (server runner or client)
+///
+/// Published retained as plain text, `offline` is also the last-will
+/// message so a crashed process is reported by the broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+    /// The connection is up
+    Online,
+    /// The connection has been closed or lost
+    Offline,
+}
+
+impl Presence {
+    /// Text published on the presence topic
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Presence::Online => "online",
+            Presence::Offline => "offline",
+        }
+    }
+
+    /// Serialize the Presence to bytes
+    pub fn to_bytes(&self) -> Bytes {
+        Bytes::from_static(self.as_str().as_bytes())
+    }
+
+    /// Deserialize a Presence from bytes
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        match bytes {
+            b"online" => Ok(Presence::Online),
+            b"offline" => Ok(Presence::Offline),
+            _ => Err(anyhow::anyhow!(
+                "Invalid presence: {}",
+                String::from_utf8_lossy(bytes)
+            )),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Line error counters reported by the serial driver of the OS
+///
+/// Only available on platforms that expose them (TIOCGICOUNT on Linux).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LineCounters {
+    /// Framing errors
+    pub frame: u64,
+    /// Parity errors
+    pub parity: u64,
+    /// Hardware overruns (UART FIFO)
+    pub overrun: u64,
+    /// Break conditions received
+    pub brk: u64,
+    /// Software buffer overruns (tty flip buffer)
+    pub buf_overrun: u64,
+}
+
+/// Traffic and error statistics of a serial port instance
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsPayload {
+    /// PZA identifier
+    pub pza_id: String,
+    /// Bytes written to the serial port
+    pub bytes_tx: u64,
+    /// Bytes read from the serial port
+    pub bytes_rx: u64,
+    /// Write operations on the serial port
+    pub messages_tx: u64,
+    /// Read chunks published from the serial port
+    pub messages_rx: u64,
+    /// Tx commands dropped because the transmit queue was full or closed
+    pub tx_queue_drops: u64,
+    /// Errors returned while reading the serial port
+    pub read_errors: u64,
+    /// Reconnections of the runner to the broker
+    pub reconnections: u64,
+    /// Line error counters, None if the platform does not provide them
+    #[serde(default)]
+    pub line: Option<LineCounters>,
+}
+
+impl StatsPayload {
+    /// Serialize the StatsPayload to JSON bytes
+    pub fn to_json_bytes(&self) -> anyhow::Result<Bytes> {
+        Ok(Bytes::from(serde_json::to_string(self)?))
+    }
+
+    /// Deserialize a StatsPayload from JSON bytes
+    pub fn from_json_bytes(bytes: Bytes) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
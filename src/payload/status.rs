@@ -2,7 +2,7 @@ use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
 /// Status of a power supply instance
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Status {
     /// The instance is starting up
     Initializing,
@@ -10,6 +10,12 @@ pub enum Status {
     Running,
     /// The instance has encountered a critical error
     Panicking,
+    /// The serial port has been lost
+    Disconnected,
+    /// The instance is waiting to be restarted
+    Reconnecting,
+    /// The instance has been stopped and will not be restarted
+    Stopped,
 }
 
 /// Status payload for communicating power supply status
@@ -23,6 +29,9 @@ pub struct StatusPayload {
     pub status: Status,
     /// Optional panic message if status is Panicking
     pub panic_message: Option<String>,
+    /// Optional details about the other states (disconnection cause, retry...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
 }
 
 impl StatusPayload {
@@ -32,6 +41,7 @@ impl StatusPayload {
             pza_id: super::generate_pza_id(),
             status,
             panic_message: None,
+            message: None,
         }
     }
 
@@ -41,6 +51,12 @@ impl StatusPayload {
         self
     }
 
+    /// Set the details message
+    pub fn with_message(mut self, message: String) -> Self {
+        self.message = Some(message);
+        self
+    }
+
     /// Serialize the StatusPayload to JSON bytes
     pub fn to_json_bytes(&self) -> anyhow::Result<Bytes> {
         Ok(Bytes::from(serde_json::to_string(self)?))
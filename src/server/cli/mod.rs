@@ -39,14 +39,14 @@ pub enum Commands {
 }
 
 /// Grouping for flags that control which services to disable when running.
-#[derive(clap::Args, Debug, Clone, PartialEq)]
+#[derive(clap::Args, Debug, Clone, PartialEq, Default)]
 pub struct ServicesOverrides {
     /// Disable the TUI
     #[arg(long = "no-tui")]
     pub no_tui: bool,
 
     /// Disable the embedded broker
-    #[arg(long = "no-broker")]
+    #[arg(long = "no-broker", conflicts_with = "broker_only")]
     pub no_broker: bool,
 
     /// Disable MCP servers
@@ -54,10 +54,18 @@ pub struct ServicesOverrides {
     pub no_mcp: bool,
 
     /// Disable runners
-    #[arg(long = "no-runners")]
+    #[arg(long = "no-runners", conflicts_with = "runners")]
     pub no_runners: bool,
 
     /// Disable traces
     #[arg(long = "no-traces")]
     pub no_traces: bool,
+
+    /// Only run the embedded broker (no runners, MCP nor TUI)
+    #[arg(long = "broker-only", conflicts_with_all = ["no_runners", "runners"])]
+    pub broker_only: bool,
+
+    /// Only start the given runner, repeat to start several
+    #[arg(long = "runner", value_name = "NAME")]
+    pub runners: Vec<String>,
 }
@@ -0,0 +1,113 @@
+use pza_toolkit::config::UsbEndpointConfig;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Baud rates tried by the automatic detection when none are configured
+pub const DEFAULT_AUTO_BAUD_CANDIDATES: [u32; 8] =
+    [9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
+
+/// Default time spent listening on each candidate rate (in milliseconds)
+pub const DEFAULT_AUTO_BAUD_DWELL_MS: u64 = 500;
+
+/// Baud rate used when nothing is configured
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+// ================
+
+/// Keyword accepted in place of a numeric baud rate
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BaudRateKeyword {
+    /// Detect the baud rate automatically when the port is opened
+    Auto,
+}
+
+// ================
+
+/// Baud rate configuration, either a fixed value or `"auto"`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BaudRateConfig {
+    /// Fixed baud rate
+    Fixed(u32),
+    /// Keyword based configuration (`"auto"`)
+    Keyword(BaudRateKeyword),
+}
+
+impl BaudRateConfig {
+    // ------------------------------------------------------------------------------
+
+    /// True if the baud rate must be detected automatically
+    pub fn is_auto(&self) -> bool {
+        matches!(self, BaudRateConfig::Keyword(BaudRateKeyword::Auto))
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Get the fixed baud rate, if any
+    pub fn fixed(&self) -> Option<u32> {
+        match self {
+            BaudRateConfig::Fixed(rate) => Some(*rate),
+            BaudRateConfig::Keyword(_) => None,
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+}
+
+// ================
+
+/// Serial port endpoint configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SerialPortEndpointConfig {
+    /// Name of the port (COM3, /dev/ttyUSB0...)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// USB identifiers used to find the port when no name is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usb: Option<UsbEndpointConfig>,
+
+    /// Baud rate of the port, a number or `"auto"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baud_rate: Option<BaudRateConfig>,
+}
+
+// ================
+
+/// Settings of the automatic baud rate detection
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AutoBaudConfig {
+    /// Candidate baud rates, tried in order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidates: Option<Vec<u32>>,
+
+    /// Optional probe string sent after switching to each candidate rate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probe: Option<String>,
+
+    /// Time spent listening on each candidate rate (in milliseconds)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dwell_ms: Option<u64>,
+}
+
+impl AutoBaudConfig {
+    // ------------------------------------------------------------------------------
+
+    /// Candidate baud rates, or the default list
+    pub fn candidates(&self) -> Vec<u32> {
+        match &self.candidates {
+            Some(candidates) if !candidates.is_empty() => candidates.clone(),
+            _ => DEFAULT_AUTO_BAUD_CANDIDATES.to_vec(),
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Listening time on each candidate rate
+    pub fn dwell(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.dwell_ms.unwrap_or(DEFAULT_AUTO_BAUD_DWELL_MS))
+    }
+
+    // ------------------------------------------------------------------------------
+}
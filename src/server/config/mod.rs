@@ -1,7 +1,12 @@
+mod endpoint;
 mod path;
 mod tui;
+pub use endpoint::AutoBaudConfig;
+pub use endpoint::BaudRateConfig;
+pub use endpoint::SerialPortEndpointConfig;
+pub use endpoint::DEFAULT_BAUD_RATE;
+pub use pza_toolkit::config::IPEndpointConfig;
 use pza_toolkit::config::MqttBrokerConfig;
-pub use pza_toolkit::config::{IPEndpointConfig, SerialPortEndpointConfig};
 use serde::{de, Deserialize, Serialize};
 use serde_json;
 use std::{any, collections::HashMap};
@@ -11,7 +16,8 @@ use pza_toolkit::dioxus::logger::LoggerBuilder;
 use tracing::{debug, Level};
 
 use crate::server::config::tui::TuiConfig;
-use pza_serial_port_client::DEFAULT_MCP_PORT;
+use crate::BrokerEndpoint;
+use crate::DEFAULT_MCP_PORT;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GuiConfig {
@@ -41,6 +47,22 @@ pub struct SerialPortConfig {
     /// Serial port configuration
     #[serde(skip_serializing_if = "Option::is_none")]
     pub endpoint: Option<SerialPortEndpointConfig>,
+
+    /// Automatic baud rate detection settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_baud: Option<AutoBaudConfig>,
+
+    /// Also publish rx data as timestamped chunks on `rx/chunks`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_rx: Option<bool>,
+
+    /// Number of rx bytes kept by the MCP server for this port (defaults to 1 MiB)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mcp_buffer_size: Option<usize>,
+
+    /// Emulator only: publish a test message on rx every 2 s (defaults to true)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_messages: Option<bool>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -56,6 +78,17 @@ pub struct ServerConfig {
 
     /// Power supply configurations, keyed by their unique identifiers
     pub runners: Option<HashMap<String, SerialPortConfig>>,
+
+    /// Runners started by this process, all the configured ones if None
+    ///
+    /// The other services still see every configured runner, so they can
+    /// work with runners started by another process on the same broker.
+    #[serde(skip)]
+    pub started_runners: Option<Vec<String>>,
+
+    /// Disable the traces, even without TUI
+    #[serde(skip)]
+    pub no_traces: bool,
 }
 
 impl Default for ServerConfig {
@@ -69,9 +102,13 @@ impl Default for ServerConfig {
                 description: None,
                 endpoint: Some(SerialPortEndpointConfig {
                     name: Some("emulator".to_string()),
-                    baud_rate: Some(9600),
+                    baud_rate: Some(BaudRateConfig::Fixed(9600)),
                     usb: None,
                 }),
+                auto_baud: None,
+                structured_rx: None,
+                mcp_buffer_size: None,
+                test_messages: None,
             },
         );
 
@@ -84,6 +121,8 @@ impl Default for ServerConfig {
             },
             broker: MqttBrokerConfig::default(),
             runners: Some(devices),
+            started_runners: None,
+            no_traces: false,
         }
     }
 }
@@ -95,25 +134,82 @@ impl ServerConfig {
         let config_path = path::server_config_file()
             .ok_or_else(|| anyhow::anyhow!("Failed to determine server configuration file path"))?;
 
-        pza_toolkit::config::read_config::<ServerConfig>(&config_path)
+        let config = pza_toolkit::config::read_config::<ServerConfig>(&config_path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check the configuration for errors the services cannot recover from
+    ///
+    /// Names starting with `_` are reserved for the aggregated MCP endpoint
+    /// (`_all`) and the shared topics (`_instances`, `_schema`, `_clients`).
+    /// Names are one MQTT topic level, so they cannot contain a level
+    /// separator, a wildcard or NUL.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for name in self.runner_names() {
+            if name.starts_with('_') {
+                return Err(anyhow::anyhow!(
+                    "Invalid runner name '{}': names starting with '_' are reserved",
+                    name
+                ));
+            }
+            if let Some(invalid) = name.chars().find(|c| matches!(c, '/' | '+' | '#' | '\0')) {
+                return Err(anyhow::anyhow!(
+                    "Invalid runner name {:?}: {:?} is not allowed in MQTT topic levels",
+                    name,
+                    invalid
+                ));
+            }
+        }
+        Ok(())
     }
 
     /// Apply service overrides from CLI arguments
     ///
-    pub fn apply_overrides(mut self, overrides: &crate::server::cli::ServicesOverrides) -> Self {
+    pub fn apply_overrides(
+        mut self,
+        overrides: &crate::server::cli::ServicesOverrides,
+    ) -> anyhow::Result<Self> {
         if self.tui.enable.is_none() {
             self.tui.enable = Some(true);
         }
-        // if overrides.no_mcp {
-        //     self.mcp.enable = false;
-        // }
         if overrides.no_tui {
             self.tui.enable = Some(false);
         }
-        // if overrides.no_runners {
-        //     self.runners = None;
-        // }
-        self
+        if overrides.no_broker {
+            self.broker.use_builtin = Some(false);
+        }
+        if overrides.no_mcp {
+            self.mcp.enable = false;
+        }
+        if overrides.no_runners {
+            self.started_runners = Some(Vec::new());
+        }
+        if overrides.no_traces {
+            self.no_traces = true;
+        }
+        if !overrides.runners.is_empty() {
+            let configured = self.runner_names();
+            if let Some(unknown) = overrides
+                .runners
+                .iter()
+                .find(|name| !configured.contains(name))
+            {
+                return Err(anyhow::anyhow!(
+                    "Unknown runner '{}', configured runners: {:?}",
+                    unknown,
+                    configured
+                ));
+            }
+            self.started_runners = Some(overrides.runners.clone());
+        }
+        if overrides.broker_only {
+            self.broker.use_builtin = Some(true);
+            self.mcp.enable = false;
+            self.tui.enable = Some(false);
+            self.started_runners = Some(Vec::new());
+        }
+        Ok(self)
     }
 
     /// List MCP server URLs from the configuration
@@ -127,12 +223,19 @@ impl ServerConfig {
                     "http://{}:{}/{}/{}",
                     self.mcp.host,
                     self.mcp.port,
-                    pza_serial_port_client::SERVER_TYPE_NAME,
+                    crate::SERVER_TYPE_NAME,
                     name
                 );
                 urls.push(url);
             }
         }
+        urls.push(format!(
+            "http://{}:{}/{}/{}",
+            self.mcp.host,
+            self.mcp.port,
+            crate::SERVER_TYPE_NAME,
+            crate::MCP_ALL_PORTS_ENDPOINT
+        ));
 
         urls
     }
@@ -156,10 +259,35 @@ impl ServerConfig {
         }
     }
 
+    /// Endpoint of the MQTT broker used by the services (localhost:1883 if not set)
+    pub fn broker_endpoint(&self) -> BrokerEndpoint {
+        self.broker
+            .tcp
+            .clone()
+            .map(BrokerEndpoint::from)
+            .unwrap_or_default()
+    }
+
+    /// Configurations of the runners to start in this process
+    pub fn started_runners_configs(&self) -> Option<HashMap<String, SerialPortConfig>> {
+        let runners = self.runners.as_ref()?;
+        match &self.started_runners {
+            None => Some(runners.clone()),
+            Some(names) if names.is_empty() => None,
+            Some(names) => Some(
+                runners
+                    .iter()
+                    .filter(|(name, _)| names.contains(name))
+                    .map(|(name, config)| (name.clone(), config.clone()))
+                    .collect(),
+            ),
+        }
+    }
+
     /// Determine if tracing should be enabled based on TUI configuration
     pub fn should_enable_tracing(&self) -> bool {
-        // Enable tracing if TUI is disabled
-        !self.tui.enable.unwrap_or(false)
+        // Enable tracing if TUI is disabled, unless traces are disabled
+        !self.no_traces && !self.tui.enable.unwrap_or(false)
     }
 
     /// Setup tracing based on the configuration
@@ -185,3 +313,163 @@ impl ServerConfig {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::cli::ServicesOverrides;
+
+    /// Default configuration with emulator runners of the given names
+    fn config_with_runners(names: &[&str]) -> ServerConfig {
+        let mut config = ServerConfig::default();
+        let runner = config.runners.as_ref().unwrap()["emulator"].clone();
+        config.runners = Some(
+            names
+                .iter()
+                .map(|name| (name.to_string(), runner.clone()))
+                .collect(),
+        );
+        config
+    }
+
+    /// Names of the runners started with the configuration, sorted
+    fn started_names(config: &ServerConfig) -> Option<Vec<String>> {
+        let mut names: Vec<String> = config.started_runners_configs()?.into_keys().collect();
+        names.sort();
+        Some(names)
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(ServerConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn reserved_runner_names_are_rejected() {
+        for name in ["_all", "_instances", "_x"] {
+            let mut config = ServerConfig::default();
+            let runner = config
+                .runners
+                .as_ref()
+                .unwrap()
+                .values()
+                .next()
+                .unwrap()
+                .clone();
+            config
+                .runners
+                .as_mut()
+                .unwrap()
+                .insert(name.to_string(), runner);
+            let error = config.validate().unwrap_err();
+            assert!(error.to_string().contains(name));
+        }
+    }
+
+    #[test]
+    fn topic_special_characters_are_rejected() {
+        for name in ["a/b", "a+", "#", "nul\0"] {
+            let error = config_with_runners(&[name]).validate().unwrap_err();
+            assert!(error.to_string().contains("not allowed"), "{}", name);
+        }
+        assert!(config_with_runners(&["dut-1.a"]).validate().is_ok());
+    }
+
+    #[test]
+    fn without_overrides_every_service_and_runner_starts() {
+        let config = config_with_runners(&["a", "b"])
+            .apply_overrides(&ServicesOverrides::default())
+            .unwrap();
+        assert_eq!(config.tui.enable, Some(true));
+        assert!(config.mcp.enable);
+        assert_eq!(started_names(&config), Some(vec!["a".into(), "b".into()]));
+    }
+
+    #[test]
+    fn broker_only_disables_the_other_services() {
+        let overrides = ServicesOverrides {
+            broker_only: true,
+            ..Default::default()
+        };
+        let config = config_with_runners(&["a"])
+            .apply_overrides(&overrides)
+            .unwrap();
+        assert_eq!(config.broker.use_builtin, Some(true));
+        assert!(!config.mcp.enable);
+        assert_eq!(config.tui.enable, Some(false));
+        assert_eq!(started_names(&config), None);
+    }
+
+    #[test]
+    fn repeated_runner_starts_only_the_selected_runners() {
+        let overrides = ServicesOverrides {
+            runners: vec!["a".into(), "c".into()],
+            ..Default::default()
+        };
+        let config = config_with_runners(&["a", "b", "c"])
+            .apply_overrides(&overrides)
+            .unwrap();
+        assert_eq!(started_names(&config), Some(vec!["a".into(), "c".into()]));
+        // The other runners stay configured, for the TUI and MCP of this process
+        assert_eq!(config.runner_names().len(), 3);
+    }
+
+    #[test]
+    fn unknown_runner_is_an_error() {
+        let overrides = ServicesOverrides {
+            runners: vec!["a".into(), "missing".into()],
+            ..Default::default()
+        };
+        let error = config_with_runners(&["a"])
+            .apply_overrides(&overrides)
+            .unwrap_err();
+        assert!(error.to_string().contains("missing"));
+    }
+
+    #[test]
+    fn no_runners_starts_none() {
+        let overrides = ServicesOverrides {
+            no_runners: true,
+            ..Default::default()
+        };
+        let config = config_with_runners(&["a"])
+            .apply_overrides(&overrides)
+            .unwrap();
+        assert_eq!(started_names(&config), None);
+        assert!(config.mcp.enable);
+    }
+
+    #[test]
+    fn no_mcp_disables_only_the_mcp_server() {
+        let overrides = ServicesOverrides {
+            no_mcp: true,
+            ..Default::default()
+        };
+        let config = config_with_runners(&["a"])
+            .apply_overrides(&overrides)
+            .unwrap();
+        assert!(!config.mcp.enable);
+        assert_eq!(config.tui.enable, Some(true));
+        assert_eq!(started_names(&config), Some(vec!["a".into()]));
+    }
+
+    #[test]
+    fn combined_overrides_are_all_applied() {
+        let overrides = ServicesOverrides {
+            no_tui: true,
+            no_broker: true,
+            no_mcp: true,
+            no_traces: true,
+            runners: vec!["b".into()],
+            ..Default::default()
+        };
+        let config = config_with_runners(&["a", "b"])
+            .apply_overrides(&overrides)
+            .unwrap();
+        assert_eq!(config.tui.enable, Some(false));
+        assert_eq!(config.broker.use_builtin, Some(false));
+        assert!(!config.mcp.enable);
+        assert!(config.no_traces);
+        assert_eq!(started_names(&config), Some(vec!["b".into()]));
+    }
+}
@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use crate::payload::AutoBaudPayload;
+use crate::payload::BaudRateScore;
+use serial2_tokio::SerialPort;
+use tracing::debug;
+use tracing::info;
+
+use super::line_counters;
+use crate::server::config::AutoBaudConfig;
+
+/// Size of the buffer used to read samples
+const SAMPLE_CHUNK_SIZE: usize = 1024;
+
+/// Maximum number of bytes kept per candidate rate
+const SAMPLE_MAX_SIZE: usize = 4096;
+
+// ================
+
+/// Score a sample received at a given baud rate
+///
+/// Printable ASCII characters (plus CR, LF and TAB) count positively.
+/// NUL and 0xFF bytes are what a UART produces most of the time when the
+/// rate is wrong, so they are counted as framing errors and penalized,
+/// together with the `line_errors` reported by the OS (if any).
+pub fn score_sample(baud_rate: u32, sample: &[u8], line_errors: usize) -> BaudRateScore {
+    let printable = sample
+        .iter()
+        .filter(|&&b| matches!(b, 0x20..=0x7E | b'\r' | b'\n' | b'\t'))
+        .count();
+    let framing_errors = sample.iter().filter(|&&b| b == 0x00 || b == 0xFF).count() + line_errors;
+
+    let score = if sample.is_empty() {
+        0.0
+    } else {
+        let len = sample.len() as f64;
+        (printable as f64 - 2.0 * framing_errors as f64) / len
+    };
+
+    BaudRateScore {
+        baud_rate,
+        bytes: sample.len(),
+        printable,
+        framing_errors,
+        score,
+    }
+}
+
+// ================
+
+/// Try every candidate baud rate on the given port and score the received data
+///
+/// The port is left configured at the best rate, or at its original rate if
+/// no candidate received usable data.
+pub async fn detect(port: &SerialPort, config: &AutoBaudConfig) -> anyhow::Result<AutoBaudPayload> {
+    let mut settings = port.get_configuration()?;
+    let original_rate = settings.get_baud_rate()?;
+
+    let mut scores = Vec::new();
+    for baud_rate in config.candidates() {
+        settings.set_baud_rate(baud_rate)?;
+        port.set_configuration(&settings)?;
+        port.discard_input_buffer()?;
+
+        if let Some(probe) = &config.probe {
+            port.write_all(probe.as_bytes()).await?;
+        }
+
+        let counters_before = line_counters::read(port);
+        let sample = read_sample(port, config.dwell()).await;
+        let line_errors = match (counters_before, line_counters::read(port)) {
+            (Some(before), Some(after)) => {
+                (after.frame.saturating_sub(before.frame)
+                    + after.parity.saturating_sub(before.parity)) as usize
+            }
+            _ => 0,
+        };
+        let score = score_sample(baud_rate, &sample, line_errors);
+        debug!(
+            "Auto-baud: {} baud -> {} bytes, score {:.3}",
+            baud_rate, score.bytes, score.score
+        );
+        scores.push(score);
+    }
+
+    let result = AutoBaudPayload::from_scores(scores);
+    settings.set_baud_rate(result.baud_rate.unwrap_or(original_rate))?;
+    port.set_configuration(&settings)?;
+    port.discard_input_buffer()?;
+
+    info!("Auto-baud: selected rate {:?}", result.baud_rate);
+    Ok(result)
+}
+
+// ================
+
+/// Read everything received on the port during the given duration
+async fn read_sample(port: &SerialPort, dwell: Duration) -> Vec<u8> {
+    let mut sample = Vec::new();
+    let mut chunk = [0u8; SAMPLE_CHUNK_SIZE];
+    let deadline = tokio::time::Instant::now() + dwell;
+
+    while sample.len() < SAMPLE_MAX_SIZE {
+        match tokio::time::timeout_at(deadline, port.read(&mut chunk)).await {
+            Ok(Ok(n)) => sample.extend_from_slice(&chunk[..n]),
+            // Read errors are frequent while switching rates, keep listening
+            Ok(Err(e)) => {
+                debug!("Auto-baud: read error: {}", e);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Err(_) => break,
+        }
+    }
+
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printable_text_scores_one() {
+        let score = score_sample(115200, b"Hello\r\n", 0);
+        assert_eq!(score.baud_rate, 115200);
+        assert_eq!(score.bytes, 7);
+        assert_eq!(score.printable, 7);
+        assert_eq!(score.framing_errors, 0);
+        assert_eq!(score.score, 1.0);
+    }
+
+    #[test]
+    fn empty_sample_scores_zero() {
+        let score = score_sample(9600, b"", 0);
+        assert_eq!(score.bytes, 0);
+        assert_eq!(score.score, 0.0);
+    }
+
+    #[test]
+    fn nul_and_ff_bytes_are_framing_errors() {
+        let score = score_sample(9600, &[b'a', 0x00, 0xFF, b'b'], 0);
+        assert_eq!(score.printable, 2);
+        assert_eq!(score.framing_errors, 2);
+        assert_eq!(score.score, (2.0 - 4.0) / 4.0);
+    }
+
+    #[test]
+    fn line_errors_are_penalized() {
+        let clean = score_sample(9600, b"abcd", 0);
+        let noisy = score_sample(9600, b"abcd", 1);
+        assert_eq!(noisy.framing_errors, 1);
+        assert!(noisy.score < clean.score);
+    }
+
+    #[test]
+    fn garbage_scores_below_text() {
+        let text = score_sample(115200, b"login: ", 0);
+        let garbage = score_sample(9600, &[0x80, 0x00, 0xFE, 0xFF, 0x13], 0);
+        assert!(text.score > garbage.score);
+        assert!(garbage.score < 0.0);
+    }
+}
@@ -1,14 +1,46 @@
 use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
 use pza_toolkit::rumqtt::client::RumqttCustomAsyncClient;
 use tracing::info;
 
+use super::stats::PortStats;
+use super::DriverContext;
 use super::SerialPortDriver;
+use crate::payload::FlowControl;
+use crate::payload::LineSettingsPayload;
+use crate::payload::Parity;
+use crate::payload::StopBits;
+use crate::server::config::BaudRateConfig;
 use crate::server::config::SerialPortConfig;
+use crate::server::config::DEFAULT_BAUD_RATE;
 
 /// A power supply emulator for testing and development purposes
 pub struct PowerSupplyEmulator {
     client: Option<RumqttCustomAsyncClient>,
+
+    /// Traffic counters shared with the runner
+    stats: Option<Arc<PortStats>>,
+
+    /// Queue of the sent data to loop back on rx
+    ///
+    /// The data is published by a separate task: `send` runs on the event
+    /// loop of the runner, it must not wait for the MQTT request channel.
+    loopback: Option<mpsc::UnboundedSender<bytes::Bytes>>,
+
+    /// Task publishing the looped back data, aborted on shutdown
+    loopback_task: Option<JoinHandle<()>>,
+
+    /// Emulated line settings, every change is accepted as is
+    line_settings: LineSettingsPayload,
+
+    /// Whether to publish a test message on rx periodically
+    test_messages: bool,
+
+    /// Task publishing the test messages, aborted on shutdown
+    test_messages_task: Option<JoinHandle<()>>,
 }
 
 impl PowerSupplyEmulator {
@@ -16,7 +48,39 @@ impl PowerSupplyEmulator {
 
     /// Create a new power supply emulator instance
     pub fn new(config: SerialPortConfig) -> Self {
-        Self { client: None }
+        let baud_rate = match config.endpoint.as_ref().and_then(|e| e.baud_rate.clone()) {
+            Some(BaudRateConfig::Fixed(rate)) => rate,
+            _ => DEFAULT_BAUD_RATE,
+        };
+        Self {
+            client: None,
+            stats: None,
+            loopback: None,
+            loopback_task: None,
+            line_settings: LineSettingsPayload {
+                baud_rate: Some(baud_rate),
+                data_bits: Some(8),
+                parity: Some(Parity::None),
+                stop_bits: Some(StopBits::One),
+                flow_control: Some(FlowControl::None),
+                ..LineSettingsPayload::request()
+            },
+            test_messages: config.test_messages.unwrap_or(true),
+            test_messages_task: None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Abort the test messages and loopback tasks
+    fn abort_tasks(&mut self) {
+        self.loopback = None;
+        for task in [self.test_messages_task.take(), self.loopback_task.take()]
+            .into_iter()
+            .flatten()
+        {
+            task.abort();
+        }
     }
 
     //--------------------------------------------------------------------------
@@ -25,7 +89,7 @@ impl PowerSupplyEmulator {
     pub fn manifest() -> serde_json::Value {
         serde_json::json!({
             "model": "emulator",
-            "description": "A simple power supply emulator for testing and development purposes.",
+            "description": "A simple emulator for testing and development purposes, sent data is looped back on rx.",
         })
     }
 }
@@ -33,38 +97,104 @@ impl PowerSupplyEmulator {
 #[async_trait]
 impl SerialPortDriver for PowerSupplyEmulator {
     /// Initialize the driver
-    async fn initialize(&mut self, mqtt_client: RumqttCustomAsyncClient) -> anyhow::Result<()> {
+    async fn initialize(&mut self, context: DriverContext) -> anyhow::Result<()> {
         info!("Emulator Driver: initialize");
 
-        self.client = Some(mqtt_client.clone());
+        self.client = Some(context.client);
+        self.stats = Some(context.stats);
+        let rx_publisher = context.rx_publisher;
+
+        // Loop the sent data back on rx, in order
+        let (loopback, mut looped_back) = mpsc::unbounded_channel::<bytes::Bytes>();
+        self.loopback = Some(loopback);
+        let loopback_publisher = rx_publisher.clone();
+        self.loopback_task = Some(tokio::spawn(async move {
+            while let Some(bytes) = looped_back.recv().await {
+                if let Err(e) = loopback_publisher
+                    .publish(bytes, std::time::SystemTime::now())
+                    .await
+                {
+                    tracing::error!("Failed to loop emulator data back: {}", e);
+                }
+            }
+        }));
+
+        if !self.test_messages {
+            return Ok(());
+        }
 
         // Spawn a task to periodically send test data on the rx topic
-        tokio::spawn(async move {
+        self.test_messages_task = Some(tokio::spawn(async move {
             let mut counter = 0u32;
             loop {
                 tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
 
                 let test_message = format!("Emulator test message #{}\n", counter);
-                let topic = mqtt_client.topic_with_prefix("rx");
 
-                if let Err(e) = mqtt_client.publish(topic, test_message.into_bytes()).await {
+                if let Err(e) = rx_publisher
+                    .publish(test_message.into(), std::time::SystemTime::now())
+                    .await
+                {
                     tracing::error!("Failed to publish emulator test message: {}", e);
                 }
 
                 counter += 1;
             }
-        });
+        }));
 
         Ok(())
     }
     /// Shutdown the driver
     async fn shutdown(&mut self) -> anyhow::Result<()> {
         info!("Emulator Driver: shutdown");
+        self.abort_tasks();
         Ok(())
     }
 
-    /// Send data to the power supply
-    async fn send(&mut self, _bytes: bytes::Bytes) -> anyhow::Result<()> {
+    /// Send data to the emulated port, it is looped back on rx
+    async fn send(&mut self, bytes: bytes::Bytes) -> anyhow::Result<()> {
+        if let Some(stats) = &self.stats {
+            stats.record_tx(bytes.len());
+        }
+        if let Some(loopback) = &self.loopback {
+            loopback
+                .send(bytes)
+                .map_err(|_| anyhow::anyhow!("Emulator loopback task stopped"))?;
+        }
         Ok(())
     }
+
+    /// Read the emulated line settings
+    async fn line_settings(&mut self) -> anyhow::Result<LineSettingsPayload> {
+        Ok(self.line_settings.clone())
+    }
+
+    /// Change the emulated line settings
+    async fn set_line_settings(
+        &mut self,
+        changes: LineSettingsPayload,
+    ) -> anyhow::Result<LineSettingsPayload> {
+        if let Some(data_bits) = changes.data_bits {
+            if !(5..=8).contains(&data_bits) {
+                return Err(anyhow::anyhow!(
+                    "Unsupported number of data bits: {}",
+                    data_bits
+                ));
+            }
+        }
+        let current = &mut self.line_settings;
+        current.baud_rate = changes.baud_rate.or(current.baud_rate);
+        current.data_bits = changes.data_bits.or(current.data_bits);
+        current.parity = changes.parity.or(current.parity);
+        current.stop_bits = changes.stop_bits.or(current.stop_bits);
+        current.flow_control = changes.flow_control.or(current.flow_control);
+        Ok(current.clone())
+    }
+}
+
+impl Drop for PowerSupplyEmulator {
+    /// Stop the background tasks with the runner that owns the driver
+    fn drop(&mut self) {
+        self.abort_tasks();
+    }
 }
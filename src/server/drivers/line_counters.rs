@@ -0,0 +1,81 @@
+use crate::payload::LineCounters;
+use serial2_tokio::SerialPort;
+
+/// Read the line error counters of a serial port
+///
+/// Returns None on platforms without TIOCGICOUNT support, or when the
+/// underlying tty driver does not implement it (e.g. pseudo terminals).
+#[cfg(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "arm",
+        target_arch = "aarch64"
+    )
+))]
+pub fn read(port: &SerialPort) -> Option<LineCounters> {
+    use std::os::unix::io::AsRawFd;
+
+    /// Request code of TIOCGICOUNT (asm-generic value, only valid on the
+    /// architectures above: MIPS, PowerPC and SPARC use other codes)
+    const TIOCGICOUNT: u64 = 0x545D;
+
+    /// Mirror of the kernel `struct serial_icounter_struct`
+    #[repr(C)]
+    #[derive(Default)]
+    struct SerialIcounter {
+        cts: libc::c_int,
+        dsr: libc::c_int,
+        rng: libc::c_int,
+        dcd: libc::c_int,
+        rx: libc::c_int,
+        tx: libc::c_int,
+        frame: libc::c_int,
+        overrun: libc::c_int,
+        parity: libc::c_int,
+        brk: libc::c_int,
+        buf_overrun: libc::c_int,
+        reserved: [libc::c_int; 9],
+    }
+
+    let mut counters = SerialIcounter::default();
+    // SAFETY: the fd is owned by `port` and stays valid during the call,
+    // and `counters` matches the layout expected by the kernel.
+    let ret = unsafe {
+        libc::ioctl(
+            port.as_raw_fd(),
+            TIOCGICOUNT as _,
+            &mut counters as *mut SerialIcounter,
+        )
+    };
+    if ret < 0 {
+        return None;
+    }
+
+    Some(LineCounters {
+        frame: counters.frame as u64,
+        parity: counters.parity as u64,
+        overrun: counters.overrun as u64,
+        brk: counters.brk as u64,
+        buf_overrun: counters.buf_overrun as u64,
+    })
+}
+
+// ================
+
+/// Read the line error counters of a serial port
+///
+/// Not supported on this platform.
+#[cfg(not(all(
+    target_os = "linux",
+    any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        target_arch = "arm",
+        target_arch = "aarch64"
+    )
+)))]
+pub fn read(_port: &SerialPort) -> Option<LineCounters> {
+    None
+}
@@ -0,0 +1,88 @@
+use crate::payload::FlowControl;
+use crate::payload::LineSettingsPayload;
+use crate::payload::Parity;
+use crate::payload::StopBits;
+use anyhow::anyhow;
+use serial2_tokio::SerialPort;
+
+/// Read the line settings of a serial port
+pub fn read(port: &SerialPort) -> anyhow::Result<LineSettingsPayload> {
+    let settings = port.get_configuration()?;
+
+    let data_bits = match settings.get_char_size()? {
+        serial2_tokio::CharSize::Bits5 => 5,
+        serial2_tokio::CharSize::Bits6 => 6,
+        serial2_tokio::CharSize::Bits7 => 7,
+        serial2_tokio::CharSize::Bits8 => 8,
+    };
+    let parity = match settings.get_parity()? {
+        serial2_tokio::Parity::None => Parity::None,
+        serial2_tokio::Parity::Odd => Parity::Odd,
+        serial2_tokio::Parity::Even => Parity::Even,
+    };
+    let stop_bits = match settings.get_stop_bits()? {
+        serial2_tokio::StopBits::One => StopBits::One,
+        serial2_tokio::StopBits::Two => StopBits::Two,
+    };
+    let flow_control = match settings.get_flow_control()? {
+        serial2_tokio::FlowControl::None => FlowControl::None,
+        serial2_tokio::FlowControl::XonXoff => FlowControl::Software,
+        serial2_tokio::FlowControl::RtsCts => FlowControl::Hardware,
+    };
+
+    Ok(LineSettingsPayload {
+        baud_rate: Some(settings.get_baud_rate()?),
+        data_bits: Some(data_bits),
+        parity: Some(parity),
+        stop_bits: Some(stop_bits),
+        flow_control: Some(flow_control),
+        ..LineSettingsPayload::request()
+    })
+}
+
+/// Apply the given line settings to a serial port, the others are kept
+///
+/// Returns the settings read back from the port, which may differ from the
+/// requested ones (e.g. a baud rate rounded by the hardware).
+pub fn apply(
+    port: &SerialPort,
+    changes: &LineSettingsPayload,
+) -> anyhow::Result<LineSettingsPayload> {
+    let mut settings = port.get_configuration()?;
+
+    if let Some(baud_rate) = changes.baud_rate {
+        settings.set_baud_rate(baud_rate)?;
+    }
+    if let Some(data_bits) = changes.data_bits {
+        settings.set_char_size(match data_bits {
+            5 => serial2_tokio::CharSize::Bits5,
+            6 => serial2_tokio::CharSize::Bits6,
+            7 => serial2_tokio::CharSize::Bits7,
+            8 => serial2_tokio::CharSize::Bits8,
+            other => return Err(anyhow!("Unsupported number of data bits: {}", other)),
+        });
+    }
+    if let Some(parity) = changes.parity {
+        settings.set_parity(match parity {
+            Parity::None => serial2_tokio::Parity::None,
+            Parity::Odd => serial2_tokio::Parity::Odd,
+            Parity::Even => serial2_tokio::Parity::Even,
+        });
+    }
+    if let Some(stop_bits) = changes.stop_bits {
+        settings.set_stop_bits(match stop_bits {
+            StopBits::One => serial2_tokio::StopBits::One,
+            StopBits::Two => serial2_tokio::StopBits::Two,
+        });
+    }
+    if let Some(flow_control) = changes.flow_control {
+        settings.set_flow_control(match flow_control {
+            FlowControl::None => serial2_tokio::FlowControl::None,
+            FlowControl::Software => serial2_tokio::FlowControl::XonXoff,
+            FlowControl::Hardware => serial2_tokio::FlowControl::RtsCts,
+        });
+    }
+
+    port.set_configuration(&settings)?;
+    read(port)
+}
@@ -1,22 +1,97 @@
+pub mod autobaud;
 pub mod emulator;
+pub mod line_counters;
+pub mod line_settings;
+pub mod rx;
 pub mod standard;
+pub mod stats;
 
+use crate::payload::AutoBaudPayload;
+use crate::payload::InstanceInfo;
+use crate::payload::LineCounters;
+use crate::payload::LineSettingsPayload;
+use crate::Topics;
 use async_trait::async_trait;
 use bytes::Bytes;
 use pza_toolkit::rumqtt::client::RumqttCustomAsyncClient;
 use thiserror::Error as ThisError;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+
+/// Receiver notified once confirmed bytes have been written (or failed to)
+pub type TxCompletion = oneshot::Receiver<anyhow::Result<()>>;
+
+/// Resources given by the runner to its driver
+#[derive(Clone)]
+pub struct DriverContext {
+    /// MQTT client of the runner, topics are prefixed with the instance name
+    pub client: RumqttCustomAsyncClient,
+    /// Topics of the instance
+    pub topics: Topics,
+    /// Traffic counters, the driver updates them from its IO path
+    pub stats: Arc<stats::PortStats>,
+    /// Publisher for the data read from the port
+    pub rx_publisher: rx::RxPublisher,
+    /// Report a fatal loss of the port, the runner then stops and gets restarted
+    pub faults: mpsc::UnboundedSender<String>,
+}
 
 #[async_trait]
 pub trait SerialPortDriver: Send + Sync {
     // --- Lifecycle management ---
 
     /// Initialize the driver
-    async fn initialize(&mut self, mqtt_client: RumqttCustomAsyncClient) -> anyhow::Result<()>;
+    async fn initialize(&mut self, context: DriverContext) -> anyhow::Result<()>;
     /// Shutdown the driver
     async fn shutdown(&mut self) -> anyhow::Result<()>;
 
     /// Send bytes through the serial port
     async fn send(&mut self, bytes: Bytes) -> anyhow::Result<()>;
+
+    /// Send bytes and get notified when they have actually been written
+    ///
+    /// The default implementation suits drivers whose `send` only returns
+    /// once the data is on the wire.
+    async fn send_confirmed(&mut self, bytes: Bytes) -> anyhow::Result<TxCompletion> {
+        self.send(bytes).await?;
+        let (done, completion) = oneshot::channel();
+        let _ = done.send(Ok(()));
+        Ok(completion)
+    }
+
+    // --- Optional features ---
+
+    /// Detect the baud rate of the connected device and switch the port to it
+    async fn detect_baud_rate(&mut self) -> anyhow::Result<AutoBaudPayload> {
+        Err(anyhow::anyhow!(
+            "Automatic baud rate detection is not supported by this driver"
+        ))
+    }
+
+    /// Read the line error counters of the port, if the platform provides them
+    async fn line_counters(&mut self) -> Option<LineCounters> {
+        None
+    }
+
+    /// Read the line settings of the port
+    async fn line_settings(&mut self) -> anyhow::Result<LineSettingsPayload> {
+        Err(anyhow::anyhow!(
+            "Line settings are not supported by this driver"
+        ))
+    }
+
+    /// Change the given line settings, keeping the others
+    ///
+    /// Returns every setting actually applied by the port.
+    async fn set_line_settings(
+        &mut self,
+        changes: LineSettingsPayload,
+    ) -> anyhow::Result<LineSettingsPayload> {
+        let _ = changes;
+        Err(anyhow::anyhow!(
+            "Line settings are not supported by this driver"
+        ))
+    }
 }
 
 use rand::{distributions::Alphanumeric, Rng};
@@ -100,6 +175,33 @@ impl Factory {
         }
     }
 
+    /// Registry description of an instance, without its status and presence
+    ///
+    /// The description falls back to the one of the driver manifest.
+    pub fn instance_info(&self, name: &str, config: &SerialPortConfig) -> InstanceInfo {
+        let description = config.description.clone().or_else(|| {
+            self.manifest
+                .get(&config.model)
+                .and_then(|manifest| manifest.get("description"))
+                .and_then(|description| description.as_str())
+                .map(|description| description.to_string())
+        });
+        let endpoint = config.endpoint.as_ref().and_then(|endpoint| {
+            endpoint
+                .name
+                .clone()
+                .or_else(|| endpoint.usb.as_ref().map(|usb| format!("{:?}", usb)))
+        });
+        InstanceInfo {
+            name: name.to_string(),
+            model: config.model.clone(),
+            description,
+            endpoint,
+            status: None,
+            presence: None,
+        }
+    }
+
     /// Scan for available devices
     pub fn scan(&self) -> HashMap<String, SerialPortConfig> {
         let mut result = HashMap::new();
@@ -120,3 +222,31 @@ impl Factory {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::config::ServerConfig;
+
+    fn emulator_config() -> SerialPortConfig {
+        ServerConfig::default().runners.unwrap()["emulator"].clone()
+    }
+
+    #[test]
+    fn instance_info_falls_back_to_the_manifest_description() {
+        let info = Factory::initialize().instance_info("dut", &emulator_config());
+        assert_eq!(info.name, "dut");
+        assert_eq!(info.model, "emulator");
+        assert!(info.description.unwrap().contains("emulator"));
+        assert_eq!(info.endpoint.as_deref(), Some("emulator"));
+        assert!(info.status.is_none());
+    }
+
+    #[test]
+    fn instance_info_prefers_the_configured_description() {
+        let mut config = emulator_config();
+        config.description = Some("Bench DUT".to_string());
+        let info = Factory::initialize().instance_info("dut", &config);
+        assert_eq!(info.description.as_deref(), Some("Bench DUT"));
+    }
+}
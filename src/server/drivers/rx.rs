@@ -0,0 +1,83 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::payload::BytesPayload;
+use crate::Topics;
+use bytes::Bytes;
+use pza_toolkit::rumqtt::client::RumqttCustomAsyncClient;
+
+use super::stats::PortStats;
+
+/// Publisher of the data received from a serial port
+///
+/// Publishes the raw bytes on `rx` and, when enabled, a structured
+/// `BytesPayload` on `rx/chunks` with capture timestamp and sequence number.
+#[derive(Clone)]
+pub struct RxPublisher {
+    /// MQTT client of the runner
+    client: RumqttCustomAsyncClient,
+    /// Name of the instance
+    instance_name: String,
+    /// serial-port/{name}/rx
+    topic_rx: String,
+    /// serial-port/{name}/rx/chunks, None if structured rx is disabled
+    topic_rx_chunks: Option<String>,
+    /// Next sequence number
+    sequence: Arc<AtomicU64>,
+    /// Traffic counters shared with the runner
+    stats: Arc<PortStats>,
+}
+
+impl RxPublisher {
+    // ------------------------------------------------------------------------------
+
+    /// Create a new rx publisher
+    pub fn new<A: Into<String>>(
+        client: RumqttCustomAsyncClient,
+        instance_name: A,
+        topics: &Topics,
+        structured: bool,
+        stats: Arc<PortStats>,
+    ) -> Self {
+        Self {
+            topic_rx: topics.rx.clone(),
+            topic_rx_chunks: structured.then(|| topics.rx_chunks.clone()),
+            client,
+            instance_name: instance_name.into(),
+            sequence: Arc::new(AtomicU64::new(0)),
+            stats,
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Publish a chunk of data read from the port at `captured_at`
+    pub async fn publish(&self, data: Bytes, captured_at: SystemTime) -> anyhow::Result<()> {
+        self.stats.record_rx(data.len());
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(topic_rx_chunks) = &self.topic_rx_chunks {
+            let timestamp_us = captured_at
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_micros() as u64)
+                .unwrap_or(0);
+            let chunk = BytesPayload::from_data(data.clone())
+                .with_timestamp_us(timestamp_us)
+                .with_sequence(sequence)
+                .with_instance(self.instance_name.clone());
+            self.client
+                .publish(topic_rx_chunks.clone(), chunk.to_json_bytes()?.to_vec())
+                .await?;
+        }
+
+        self.client
+            .publish(self.topic_rx.clone(), data.to_vec())
+            .await?;
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------------------
+}
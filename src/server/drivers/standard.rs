@@ -1,17 +1,44 @@
 use async_trait::async_trait;
 use std::sync::Arc;
 use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 
 use anyhow::anyhow;
 use tracing::info;
 
+use super::autobaud;
+use super::line_counters;
+use super::line_settings;
+use super::stats::PortStats;
+use super::DriverContext;
 use super::SerialPortDriver;
+use super::TxCompletion;
+use crate::payload::AutoBaudPayload;
+use crate::payload::LineCounters;
+use crate::payload::LineSettingsPayload;
+use crate::server::config::BaudRateConfig;
 use crate::server::config::SerialPortConfig;
+use crate::server::config::DEFAULT_BAUD_RATE;
 use pza_toolkit::config::UsbEndpointConfig;
 use pza_toolkit::rumqtt::client::RumqttCustomAsyncClient;
 use serial2_tokio::SerialPort;
 use tracing::debug;
+
+/// Maximum number of tx commands waiting to be written to the port
+const TX_QUEUE_CAPACITY: usize = 256;
+
+/// Consecutive read errors after which the port is considered lost
+const MAX_CONSECUTIVE_READ_ERRORS: u32 = 10;
+
+/// Data queued for the IO task, with an optional completion notifier
+struct TxRequest {
+    /// Bytes to write
+    data: bytes::Bytes,
+    /// Notified once the bytes are written, if the sender asked for it
+    done: Option<oneshot::Sender<anyhow::Result<()>>>,
+}
+
 ///
 pub struct StandardDriver {
     /// Configuration
@@ -22,7 +49,10 @@ pub struct StandardDriver {
     client: Option<RumqttCustomAsyncClient>,
 
     // Channel for sending data to the serial port
-    tx_sender: Option<mpsc::UnboundedSender<bytes::Bytes>>,
+    tx_sender: Option<mpsc::Sender<TxRequest>>,
+
+    /// Traffic counters shared with the runner
+    stats: Option<Arc<PortStats>>,
 }
 
 impl StandardDriver {
@@ -33,6 +63,7 @@ impl StandardDriver {
             driver: None,
             client: None,
             tx_sender: None,
+            stats: None,
         }
     }
 
@@ -48,6 +79,36 @@ impl StandardDriver {
 
     //--------------------------------------------------------------------------
 
+    /// Queue a tx request for the IO task
+    fn queue(&self, request: TxRequest) -> anyhow::Result<()> {
+        debug!("-- try sending serial data: {}", request.data.len());
+        let len = request.data.len();
+
+        if let Some(tx_sender) = &self.tx_sender {
+            // Send data through the channel to the unified task
+            if let Err(e) = tx_sender.try_send(request) {
+                if let Some(stats) = &self.stats {
+                    stats.record_tx_queue_drop();
+                }
+                return Err(match e {
+                    mpsc::error::TrySendError::Full(_) => {
+                        anyhow!("Serial port transmit queue is full")
+                    }
+                    mpsc::error::TrySendError::Closed(_) => {
+                        anyhow!("Failed to send data to serial port task")
+                    }
+                });
+            }
+
+            debug!("-- Queued {} bytes for serial transmission", len);
+            Ok(())
+        } else {
+            Err(anyhow!("Serial port not initialized"))
+        }
+    }
+
+    //--------------------------------------------------------------------------
+
     /// Scan for available devices
     pub fn scan() -> Vec<SerialPortConfig> {
         let mut result = Vec::new();
@@ -76,8 +137,12 @@ impl StandardDriver {
                 endpoint: Some(crate::server::config::SerialPortEndpointConfig {
                     name: name,
                     usb: usb,
-                    baud_rate: Some(115200),
+                    baud_rate: Some(BaudRateConfig::Fixed(DEFAULT_BAUD_RATE)),
                 }),
+                auto_baud: None,
+                structured_rx: None,
+                mcp_buffer_size: None,
+                test_messages: None,
             });
         });
 
@@ -88,8 +153,16 @@ impl StandardDriver {
 #[async_trait]
 impl SerialPortDriver for StandardDriver {
     /// Initialize the driver
-    async fn initialize(&mut self, mqtt_client: RumqttCustomAsyncClient) -> anyhow::Result<()> {
-        self.client = Some(mqtt_client);
+    async fn initialize(&mut self, context: DriverContext) -> anyhow::Result<()> {
+        let DriverContext {
+            client,
+            topics,
+            stats,
+            rx_publisher,
+            faults,
+        } = context;
+        self.client = Some(client);
+        self.stats = Some(stats.clone());
 
         // Determine the port name from configuration
         let port_name = match &self.config.endpoint {
@@ -132,12 +205,18 @@ impl SerialPortDriver for StandardDriver {
         };
 
         // Get baud rate from configuration or use default
-        let baud_rate = self
+        let baud_rate_config = self
             .config
             .endpoint
             .as_ref()
-            .and_then(|e| e.baud_rate)
-            .unwrap_or(115200);
+            .and_then(|e| e.baud_rate.clone());
+        let auto_baud = baud_rate_config
+            .as_ref()
+            .map_or(false, BaudRateConfig::is_auto);
+        let baud_rate = match &baud_rate_config {
+            Some(BaudRateConfig::Fixed(rate)) => *rate,
+            _ => DEFAULT_BAUD_RATE,
+        };
 
         // Open the serial port
         let port = SerialPort::open(&port_name, baud_rate)?;
@@ -148,31 +227,50 @@ impl SerialPortDriver for StandardDriver {
             port_name, baud_rate
         );
 
+        // Detect the baud rate before starting the IO task if requested
+        if auto_baud {
+            let result = self.detect_baud_rate().await?;
+            if let Some(client) = &self.client {
+                client
+                    .publish(topics.autobaud.clone(), result.to_json_bytes()?.to_vec())
+                    .await?;
+            }
+        }
+
         // Create channel for sending data
-        let (tx_sender, mut tx_receiver) = mpsc::unbounded_channel::<bytes::Bytes>();
+        let (tx_sender, mut tx_receiver) = mpsc::channel::<TxRequest>(TX_QUEUE_CAPACITY);
         self.tx_sender = Some(tx_sender);
 
         // Spawn a unified task for both reading and writing to/from the serial port
-        if let (Some(driver), Some(client)) = (self.driver.clone(), self.client.clone()) {
+        if let Some(driver) = self.driver.clone() {
             tokio::spawn(async move {
                 let mut read_buffer = [0u8; 1024];
+                let mut consecutive_read_errors = 0u32;
 
                 loop {
                     tokio::select! {
                         // Handle incoming data to send to serial port
                         data_to_send = tx_receiver.recv() => {
-                            if let Some(data) = data_to_send {
+                            if let Some(TxRequest { data, done }) = data_to_send {
                                 let mut port = driver.lock().await;
                                 use tokio::io::AsyncWriteExt;
 
-                                if let Err(e) = port.write_all(&data).await {
+                                let result = if let Err(e) = port.write_all(&data).await {
                                     tracing::error!("Error writing to serial port: {}", e);
+                                    Err(anyhow!("Error writing to serial port: {}", e))
                                 } else if let Err(e) = port.flush().await {
                                     tracing::error!("Error flushing serial port: {}", e);
+                                    Err(anyhow!("Error flushing serial port: {}", e))
                                 } else {
+                                    stats.record_tx(data.len());
                                     info!("Sent {} bytes to serial port", data.len());
-                                }
+                                    Ok(())
+                                };
                                 drop(port); // Release the lock explicitly
+
+                                if let Some(done) = done {
+                                    let _ = done.send(result);
+                                }
                             } else {
                                 // Channel closed, exit
                                 break;
@@ -180,23 +278,25 @@ impl SerialPortDriver for StandardDriver {
                         }
 
                         // Handle reading from serial port (with timeout to avoid blocking)
-                        read_result = async {
+                        (read_result, captured_at) = async {
                             let port = driver.lock().await;
                             use tokio::io::AsyncReadExt;
                             let result = tokio::time::timeout(
                                 tokio::time::Duration::from_millis(10),
                                 port.read(&mut read_buffer)
                             ).await;
+                            // Timestamp taken as close as possible to the read
+                            let captured_at = std::time::SystemTime::now();
                             drop(port); // Release the lock explicitly
-                            result
+                            (result, captured_at)
                         } => {
                             match read_result {
                                 Ok(Ok(bytes_read)) if bytes_read > 0 => {
+                                    consecutive_read_errors = 0;
                                     // Convert the read data to bytes and publish via MQTT
                                     let data = bytes::Bytes::copy_from_slice(&read_buffer[..bytes_read]);
-                                    let topic = client.topic_with_prefix("rx");
 
-                                    if let Err(e) = client.publish(topic, data.to_vec()).await {
+                                    if let Err(e) = rx_publisher.publish(data, captured_at).await {
                                         tracing::error!("Failed to publish serial data to MQTT: {}", e);
                                     }
                                 }
@@ -204,7 +304,15 @@ impl SerialPortDriver for StandardDriver {
                                     // No data read, continue loop
                                 }
                                 Ok(Err(e)) => {
+                                    stats.record_read_error();
                                     tracing::error!("Error reading from serial port: {}", e);
+
+                                    // The device has most likely been unplugged
+                                    consecutive_read_errors += 1;
+                                    if consecutive_read_errors >= MAX_CONSECUTIVE_READ_ERRORS {
+                                        let _ = faults.send(format!("Serial port lost: {}", e));
+                                        break;
+                                    }
                                     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                                 }
                                 Err(_) => {
@@ -227,18 +335,64 @@ impl SerialPortDriver for StandardDriver {
     }
 
     async fn send(&mut self, bytes: bytes::Bytes) -> anyhow::Result<()> {
-        debug!("-- try sending serial data: {}", bytes.len());
+        self.queue(TxRequest {
+            data: bytes,
+            done: None,
+        })
+    }
 
-        if let Some(tx_sender) = &self.tx_sender {
-            // Send data through the channel to the unified task
-            tx_sender
-                .send(bytes.clone())
-                .map_err(|_| anyhow!("Failed to send data to serial port task"))?;
+    /// Queue bytes and return a receiver notified once they are written
+    async fn send_confirmed(&mut self, bytes: bytes::Bytes) -> anyhow::Result<TxCompletion> {
+        let (done, completion) = oneshot::channel();
+        self.queue(TxRequest {
+            data: bytes,
+            done: Some(done),
+        })?;
+        Ok(completion)
+    }
 
-            debug!("-- Queued {} bytes for serial transmission", bytes.len());
-            Ok(())
-        } else {
-            Err(anyhow!("Serial port not initialized"))
-        }
+    /// Detect the baud rate by trying every configured candidate
+    async fn detect_baud_rate(&mut self) -> anyhow::Result<AutoBaudPayload> {
+        let driver = self
+            .driver
+            .clone()
+            .ok_or_else(|| anyhow!("Serial port not initialized"))?;
+        let auto_baud_config = self.config.auto_baud.clone().unwrap_or_default();
+
+        // Holding the port lock pauses the IO task during the detection
+        let port = driver.lock().await;
+        autobaud::detect(&port, &auto_baud_config).await
+    }
+
+    /// Read the line error counters of the port
+    async fn line_counters(&mut self) -> Option<LineCounters> {
+        let driver = self.driver.clone()?;
+        let port = driver.lock().await;
+        line_counters::read(&port)
+    }
+
+    /// Read the line settings of the port
+    async fn line_settings(&mut self) -> anyhow::Result<LineSettingsPayload> {
+        let driver = self
+            .driver
+            .clone()
+            .ok_or_else(|| anyhow!("Serial port not initialized"))?;
+        let port = driver.lock().await;
+        line_settings::read(&port)
+    }
+
+    /// Change the given line settings of the port
+    async fn set_line_settings(
+        &mut self,
+        changes: LineSettingsPayload,
+    ) -> anyhow::Result<LineSettingsPayload> {
+        let driver = self
+            .driver
+            .clone()
+            .ok_or_else(|| anyhow!("Serial port not initialized"))?;
+        let port = driver.lock().await;
+        let applied = line_settings::apply(&port, &changes)?;
+        info!("Applied line settings: {:?}", applied);
+        Ok(applied)
     }
 }
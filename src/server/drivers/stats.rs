@@ -0,0 +1,85 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use crate::payload::LineCounters;
+use crate::payload::StatsPayload;
+
+/// Traffic and error counters of one serial port instance
+///
+/// Shared between the runner (which publishes them) and the driver
+/// (which updates them from its IO task).
+#[derive(Debug, Default)]
+pub struct PortStats {
+    /// Bytes written to the serial port
+    bytes_tx: AtomicU64,
+    /// Bytes read from the serial port
+    bytes_rx: AtomicU64,
+    /// Write operations on the serial port
+    messages_tx: AtomicU64,
+    /// Read chunks published from the serial port
+    messages_rx: AtomicU64,
+    /// Tx commands dropped before reaching the port
+    tx_queue_drops: AtomicU64,
+    /// Errors returned while reading the serial port
+    read_errors: AtomicU64,
+    /// Reconnections to the broker
+    reconnections: AtomicU64,
+}
+
+impl PortStats {
+    // ------------------------------------------------------------------------------
+
+    /// Record data written to the port
+    pub fn record_tx(&self, len: usize) {
+        self.bytes_tx.fetch_add(len as u64, Ordering::Relaxed);
+        self.messages_tx.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Record data read from the port
+    pub fn record_rx(&self, len: usize) {
+        self.bytes_rx.fetch_add(len as u64, Ordering::Relaxed);
+        self.messages_rx.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Record a tx command dropped before reaching the port
+    pub fn record_tx_queue_drop(&self) {
+        self.tx_queue_drops.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Record a read error on the port
+    pub fn record_read_error(&self) {
+        self.read_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Record a reconnection to the broker
+    pub fn record_reconnection(&self) {
+        self.reconnections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Build a payload from the current counter values
+    pub fn snapshot(&self, line: Option<LineCounters>) -> StatsPayload {
+        StatsPayload {
+            pza_id: crate::payload::generate_pza_id(),
+            bytes_tx: self.bytes_tx.load(Ordering::Relaxed),
+            bytes_rx: self.bytes_rx.load(Ordering::Relaxed),
+            messages_tx: self.messages_tx.load(Ordering::Relaxed),
+            messages_rx: self.messages_rx.load(Ordering::Relaxed),
+            tx_queue_drops: self.tx_queue_drops.load(Ordering::Relaxed),
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            reconnections: self.reconnections.load(Ordering::Relaxed),
+            line,
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+}
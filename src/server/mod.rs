@@ -1,6 +1,7 @@
 pub mod cli;
 pub mod config;
 pub mod drivers;
+pub mod mqtt;
 pub mod services;
 
 use clap::Parser;
@@ -38,8 +39,8 @@ pub async fn run_server() {
         cli::Commands::Run { services } => {
             // Load server configuration
             let server_config = ServerConfig::from_user_file()
+                .and_then(|config| config.apply_overrides(&services))
                 .unwrap_or_else(|err| panic!("Failed to load server configuration: {}", err))
-                .apply_overrides(&services)
                 .setup_tracing()
                 .trace_config();
 
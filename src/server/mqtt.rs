@@ -0,0 +1,20 @@
+use crate::payload::generate_random_string;
+use crate::BrokerEndpoint;
+use crate::SerialPortClientBuilder;
+use rumqttc::AsyncClient;
+use rumqttc::EventLoop;
+
+/// Capacity of the request channel between the MQTT client and its event loop
+const REQUEST_CHANNEL_CAPACITY: usize = 100;
+
+/// Create a MQTT client of the server on the given broker
+///
+/// The client id is derived from the name with a random suffix, so several
+/// servers can share the same broker.
+pub fn init_client(broker: &BrokerEndpoint, name: &str) -> (AsyncClient, EventLoop) {
+    let options = SerialPortClientBuilder::default()
+        .with_ip(broker.clone())
+        .with_client_id(format!("{}-{}", name, generate_random_string(5)))
+        .mqtt_options();
+    AsyncClient::new(options, REQUEST_CHANNEL_CAPACITY)
+}
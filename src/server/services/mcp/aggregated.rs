@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use rmcp::handler::server::router::prompt::PromptRouter;
+use rmcp::handler::server::router::tool::ToolRouter;
+use rmcp::handler::server::wrapper::Parameters;
+use rmcp::model::*;
+use rmcp::prompt;
+use rmcp::prompt_handler;
+use rmcp::prompt_router;
+use rmcp::service::RequestContext;
+use rmcp::tool;
+use rmcp::tool_handler;
+use rmcp::tool_router;
+use rmcp::ErrorData as McpError;
+use rmcp::RoleServer;
+use rmcp::ServerHandler;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::MCP_ALL_PORTS_ENDPOINT;
+
+use super::patterns::WaitForPatternsParams;
+use super::port::GetLineSettingsParams;
+use super::port::Port;
+use super::port::ReadBytesParams;
+use super::port::ReadTextParams;
+use super::port::SendBytesParams;
+use super::port::SendTextParams;
+use super::port::SetLineSettingsParams;
+use super::port::WaitForTextParams;
+use super::prompts::AtModemPromptParams;
+use super::prompts::BootLogPromptParams;
+use super::prompts::IdentifyDevicePromptParams;
+use super::prompts::LinuxLoginPromptParams;
+use super::resources;
+use super::resources::Subscriptions;
+use super::sessions::ListSessionsParams;
+use super::sessions::Session;
+use super::transact::SendAndWaitParams;
+
+/// Parameters of a tool applied to one of the serial ports
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct InstanceParams<T> {
+    /// Name of the serial port instance (see list_ports)
+    instance: String,
+    #[serde(flatten)]
+    params: T,
+}
+
+/// MCP service giving access to every serial port of the server
+///
+/// Same tools as the per-instance endpoints, with an `instance` parameter,
+/// so one agent session can work with several ports at once.
+#[derive(Clone)]
+pub struct PortsService {
+    /// Tool router for MCP tools
+    tool_router: ToolRouter<PortsService>,
+    /// Prompt router for MCP prompts
+    prompt_router: PromptRouter<PortsService>,
+
+    /// Served ports with the read positions of the session, keyed by instance name
+    ports: Arc<BTreeMap<String, Port>>,
+    /// MCP session of this service, unregistered from the ports once dropped
+    session: Arc<Session>,
+    /// Log resources subscribed by the session
+    subscriptions: Subscriptions,
+}
+
+impl PortsService {
+    //--------------------------------------------------------------------------
+
+    /// Create the service of a new MCP session
+    pub fn new(ports: &BTreeMap<String, Port>) -> Self {
+        let session = Session::open(MCP_ALL_PORTS_ENDPOINT, ports.values().cloned());
+        let ports = session
+            .ports()
+            .iter()
+            .map(|port| (port.name().to_string(), port.clone()))
+            .collect();
+        Self {
+            tool_router: Self::tool_router(),
+            prompt_router: Self::prompt_router(),
+            ports: Arc::new(ports),
+            session: Arc::new(session),
+            subscriptions: Subscriptions::default(),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Find the port of an instance
+    fn port(&self, instance: &str) -> Result<&Port, McpError> {
+        self.ports.get(instance).ok_or_else(|| {
+            McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!(
+                    "Unknown serial port '{}', available: {:?}",
+                    instance,
+                    self.ports.keys().collect::<Vec<_>>()
+                ),
+                None,
+            )
+        })
+    }
+
+    /// Find the port and the log kind of a resource URI
+    fn resource(&self, uri: &str) -> Result<(&Port, resources::LogKind), McpError> {
+        resources::parse_uri(uri)
+            .and_then(|(name, kind)| self.ports.get(name).map(|port| (port, kind)))
+            .ok_or_else(|| resources::not_found(uri))
+    }
+}
+
+#[tool_router]
+impl PortsService {
+    //--------------------------------------------------------------------------
+
+    /// List the serial ports of the server
+    #[tool(
+        description = "List the serial ports available on this server with their name, model, description and status. Use the name as the 'instance' parameter of the other tools."
+    )]
+    async fn list_ports(&self) -> Result<CallToolResult, McpError> {
+        let ports: Vec<_> = self.ports.values().map(Port::info).collect();
+        let json = serde_json::to_string_pretty(&ports).map_err(|e| {
+            McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Failed to serialize the ports: {}", e),
+                None,
+            )
+        })?;
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+
+    /// Send data to a serial port
+    #[tool(
+        description = "Send byte data to a serial port. Data should be provided as a hexadecimal string (e.g., '48656c6c6f' for 'Hello')"
+    )]
+    async fn send_byte_data(
+        &self,
+        params: Parameters<InstanceParams<SendBytesParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.send_byte_data(params).await
+    }
+
+    /// Send text data to a serial port
+    #[tool(
+        description = "Send text data to a serial port. The text will be converted to bytes using UTF-8 encoding."
+    )]
+    async fn send_text_data(
+        &self,
+        params: Parameters<InstanceParams<SendTextParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.send_text_data(params).await
+    }
+
+    /// Read byte data from a serial port buffer
+    #[tool(
+        description = "Read byte data that has been received from a serial port. Returns data as hexadecimal string, with a cursor to pass to the next read to get only the data received since."
+    )]
+    async fn read_byte_data(
+        &self,
+        params: Parameters<InstanceParams<ReadBytesParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.read_byte_data(params).await
+    }
+
+    /// Read text data from a serial port buffer
+    #[tool(
+        description = "Read text data that has been received from a serial port. Returns data as UTF-8 string, with a cursor to pass to the next read to get only the data received since."
+    )]
+    async fn read_text_data(
+        &self,
+        params: Parameters<InstanceParams<ReadTextParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.read_text_data(params).await
+    }
+
+    /// Wait for specific text to arrive on a serial port
+    #[tool(
+        description = "Wait for a specific text string to be received from a serial port within a timeout period. Only data received after the call is searched, or after the cursor of a previous read if given."
+    )]
+    async fn wait_for_text(
+        &self,
+        params: Parameters<InstanceParams<WaitForTextParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.wait_for_text(params).await
+    }
+
+    /// Read the line settings of a serial port
+    #[tool(
+        description = "Read the line settings of a serial port: baud rate, data bits, parity, stop bits and flow control."
+    )]
+    async fn get_line_settings(
+        &self,
+        params: Parameters<InstanceParams<GetLineSettingsParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.get_line_settings(params).await
+    }
+
+    /// Change the line settings of a serial port
+    #[tool(
+        description = "Change the line settings of a serial port (baud rate, data bits, parity, stop bits, flow control). Settings not given are kept. Returns the settings actually applied."
+    )]
+    async fn set_line_settings(
+        &self,
+        params: Parameters<InstanceParams<SetLineSettingsParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.set_line_settings(params).await
+    }
+
+    /// Send data to a serial port and wait for the response
+    #[tool(
+        description = "Send text or hex data to a serial port, then wait for the response: until a literal text, a regex match, a byte count or an idle period (first condition met, 500ms idle if none given). Returns exactly the bytes received after the send, the buffer content does not matter."
+    )]
+    async fn send_and_wait(
+        &self,
+        params: Parameters<InstanceParams<SendAndWaitParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.send_and_wait(params).await
+    }
+
+    /// Wait for one of several patterns on a serial port
+    #[tool(
+        description = "Wait until one of several labeled patterns (literal text or regex) is received from a serial port. Returns the label of the earliest match, its capture groups, the text received before it and the cursor following it, or a timeout message. Only data received after the call is searched, or after the cursor of a previous read if given."
+    )]
+    async fn wait_for_patterns(
+        &self,
+        params: Parameters<InstanceParams<WaitForPatternsParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?.wait_for_patterns(params).await
+    }
+
+    /// List the MCP sessions connected to a serial port
+    #[tool(
+        description = "List the MCP sessions connected to a serial port, on its own endpoint or this one: client, connection time and unread bytes. Each session has its own buffer read position."
+    )]
+    async fn list_sessions(
+        &self,
+        params: Parameters<InstanceParams<ListSessionsParams>>,
+    ) -> Result<CallToolResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        self.port(&instance)?
+            .list_sessions(params, self.session.id())
+            .await
+    }
+}
+
+#[prompt_router]
+impl PortsService {
+    //--------------------------------------------------------------------------
+
+    /// Review the boot log of a device
+    #[prompt(
+        name = "inspect_boot_log",
+        description = "Inspect the boot log of the device on a serial port for errors, pre-filled with the recent console output."
+    )]
+    async fn inspect_boot_log(
+        &self,
+        params: Parameters<InstanceParams<BootLogPromptParams>>,
+    ) -> Result<GetPromptResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        Ok(self.port(&instance)?.boot_log_prompt(params).await)
+    }
+
+    /// Log in to a Linux shell
+    #[prompt(
+        name = "linux_login",
+        description = "Log in to a Linux shell on a serial port, pre-filled with the recent console output."
+    )]
+    async fn linux_login(
+        &self,
+        params: Parameters<InstanceParams<LinuxLoginPromptParams>>,
+    ) -> Result<GetPromptResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        Ok(self.port(&instance)?.linux_login_prompt(params).await)
+    }
+
+    /// Drive an AT command session
+    #[prompt(
+        name = "at_modem_session",
+        description = "Drive an AT command session with a modem on a serial port, pre-filled with the recent console output."
+    )]
+    async fn at_modem_session(
+        &self,
+        params: Parameters<InstanceParams<AtModemPromptParams>>,
+    ) -> Result<GetPromptResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        Ok(self.port(&instance)?.at_modem_prompt(params).await)
+    }
+
+    /// Identify a connected device
+    #[prompt(
+        name = "identify_device",
+        description = "Identify the unknown device connected to a serial port, pre-filled with the recent console output."
+    )]
+    async fn identify_device(
+        &self,
+        params: Parameters<InstanceParams<IdentifyDevicePromptParams>>,
+    ) -> Result<GetPromptResult, McpError> {
+        let InstanceParams { instance, params } = params.0;
+        Ok(self.port(&instance)?.identify_device_prompt(params).await)
+    }
+}
+
+#[tool_handler]
+#[prompt_handler]
+impl ServerHandler for PortsService {
+    //--------------------------------------------------------------------------
+
+    /// Get server information and capabilities
+    fn get_info(&self) -> ServerInfo {
+        debug!("MCP get_info called");
+
+        ServerInfo {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_prompts()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .build(),
+            server_info: Implementation::from_build_env(),
+            instructions: Some(format!(
+                r#"""This server provides access to several serial ports: {:?}.
+Call list_ports to get their details, then give the name of the port as the 'instance' parameter of the other tools.
+            """#,
+                self.ports.keys().collect::<Vec<_>>()
+            )),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Record the client of the session, then answer as the default handler
+    async fn initialize(
+        &self,
+        request: InitializeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<InitializeResult, McpError> {
+        self.session.set_client(&request);
+        if context.peer.peer_info().is_none() {
+            context.peer.set_peer_info(request);
+        }
+        Ok(self.get_info())
+    }
+
+    /// List the log resources of every serial port
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        Ok(ListResourcesResult {
+            resources: self.ports.values().flat_map(resources::list).collect(),
+            next_cursor: None,
+        })
+    }
+
+    /// Read a log resource of a serial port
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let (port, kind) = self.resource(&request.uri)?;
+        Ok(resources::read(port, kind, request.uri).await)
+    }
+
+    /// Notify the session when a log resource receives new data
+    async fn subscribe(
+        &self,
+        request: SubscribeRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        let (port, kind) = self.resource(&request.uri)?;
+        self.subscriptions
+            .subscribe(port, kind, request.uri, context.peer)
+            .await;
+        Ok(())
+    }
+
+    /// Stop notifying the session about a log resource
+    async fn unsubscribe(
+        &self,
+        request: UnsubscribeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        self.subscriptions.unsubscribe(&request.uri).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use bytes::Buf;
+use bytes::Bytes;
+use bytes::BytesMut;
+use tokio::sync::watch;
+use tokio::sync::Mutex;
+
+/// Data read from a history since a cursor
+pub struct HistoryRead {
+    /// Bytes still kept after the cursor
+    pub data: Bytes,
+    /// Cursor of the first byte of `data`
+    pub start: u64,
+    /// Number of bytes after the cursor already dropped from the history
+    pub dropped: u64,
+    /// Number of chunks after the cursor lost before reaching the history
+    pub lost_chunks: u64,
+}
+
+impl HistoryRead {
+    /// Cursor following the last byte of `data`
+    pub fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+}
+
+/// Bounded history of the data flowing in one direction of a port
+///
+/// The history is never consumed: it keeps the last `capacity` bytes, and
+/// readers follow it with cursors, the number of bytes pushed before a
+/// position. Every push bumps that counter, so watchers are told about new
+/// data.
+#[derive(Clone)]
+pub struct History {
+    /// Last bytes of the direction, with the losses among them
+    kept: Arc<Mutex<Kept>>,
+    /// Total number of bytes pushed since the start
+    total: Arc<watch::Sender<u64>>,
+    /// Maximum number of bytes kept
+    capacity: usize,
+}
+
+/// Content of a `History`
+#[derive(Default)]
+struct Kept {
+    /// Last bytes of the direction
+    data: BytesMut,
+    /// Chunks lost before reaching the history, as the cursor of the byte
+    /// that followed them and their number, oldest first
+    losses: VecDeque<(u64, u64)>,
+}
+
+impl History {
+    //--------------------------------------------------------------------------
+
+    /// Create an empty history keeping up to `capacity` bytes
+    pub fn new(capacity: usize) -> Self {
+        let (total, _) = watch::channel(0);
+        Self {
+            kept: Arc::new(Mutex::new(Kept::default())),
+            total: Arc::new(total),
+            capacity,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Append data, dropping the oldest bytes beyond the capacity
+    pub async fn push(&self, bytes: &[u8]) {
+        self.push_after_loss(bytes, 0).await;
+    }
+
+    /// Append data that follows `lost_chunks` chunks which never reached the history
+    ///
+    /// The size of the lost chunks is unknown, so they are recorded at the
+    /// cursor of `bytes` and reported by the reads from a cursor before it.
+    pub async fn push_after_loss(&self, bytes: &[u8], lost_chunks: u64) {
+        let mut kept = self.kept.lock().await;
+        if lost_chunks > 0 {
+            kept.losses.push_back((self.end(), lost_chunks));
+        }
+        kept.data.extend_from_slice(bytes);
+        if kept.data.len() > self.capacity {
+            let excess = kept.data.len() - self.capacity;
+            kept.data.advance(excess);
+        }
+        self.total.send_modify(|total| *total += bytes.len() as u64);
+
+        // Losses before the oldest byte kept are reported as dropped bytes
+        let first = self.first();
+        while kept.losses.front().is_some_and(|(at, _)| *at < first) {
+            kept.losses.pop_front();
+        }
+    }
+
+    /// Copy of the bytes currently kept
+    pub async fn snapshot(&self) -> Bytes {
+        Bytes::copy_from_slice(&self.kept.lock().await.data)
+    }
+
+    /// Bytes pushed since a cursor, with the number of bytes already dropped
+    /// and of chunks lost
+    ///
+    /// A cursor beyond the end of the history reads nothing.
+    pub async fn read_since(&self, cursor: u64) -> HistoryRead {
+        let kept = self.kept.lock().await;
+        let end = *self.total.borrow();
+        let first = end - kept.data.len() as u64;
+        let start = cursor.clamp(first, end);
+        HistoryRead {
+            data: Bytes::copy_from_slice(&kept.data[(start - first) as usize..]),
+            start,
+            dropped: start - cursor.min(start),
+            lost_chunks: kept
+                .losses
+                .iter()
+                .filter(|(at, _)| *at >= cursor)
+                .map(|(_, chunks)| chunks)
+                .sum(),
+        }
+    }
+
+    /// Cursor of the oldest byte kept
+    pub fn first(&self) -> u64 {
+        self.end().saturating_sub(self.capacity as u64)
+    }
+
+    /// Cursor following the last byte pushed
+    pub fn end(&self) -> u64 {
+        *self.total.borrow()
+    }
+
+    /// Watch the total number of bytes pushed, changed on every push
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.total.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_since_returns_the_data_after_the_cursor() {
+        let history = History::new(16);
+        history.push(b"hello ").await;
+        history.push(b"world").await;
+        let read = history.read_since(6).await;
+        assert_eq!(&read.data[..], b"world");
+        assert_eq!(read.start, 6);
+        assert_eq!(read.end(), 11);
+        assert_eq!(read.dropped, 0);
+        assert_eq!(read.lost_chunks, 0);
+        assert_eq!(history.end(), 11);
+    }
+
+    #[tokio::test]
+    async fn read_since_reports_the_dropped_bytes() {
+        let history = History::new(4);
+        history.push(b"abcdef").await;
+        history.push(b"gh").await;
+        assert_eq!(history.first(), 4);
+        let read = history.read_since(1).await;
+        assert_eq!(&read.data[..], b"efgh");
+        assert_eq!(read.start, 4);
+        assert_eq!(read.dropped, 3);
+    }
+
+    #[tokio::test]
+    async fn read_since_beyond_the_end_reads_nothing() {
+        let history = History::new(8);
+        history.push(b"abc").await;
+        let read = history.read_since(10).await;
+        assert!(read.data.is_empty());
+        assert_eq!(read.start, 3);
+        assert_eq!(read.dropped, 0);
+    }
+
+    #[tokio::test]
+    async fn read_since_reports_the_lost_chunks() {
+        let history = History::new(8);
+        history.push(b"abc").await;
+        history.push_after_loss(b"def", 2).await;
+        assert_eq!(history.read_since(0).await.lost_chunks, 2);
+        assert_eq!(history.read_since(3).await.lost_chunks, 2);
+        let read = history.read_since(4).await;
+        assert_eq!(read.lost_chunks, 0);
+        assert_eq!(&read.data[..], b"ef");
+    }
+
+    #[tokio::test]
+    async fn lost_chunks_are_forgotten_with_their_data() {
+        let history = History::new(4);
+        history.push_after_loss(b"abc", 1).await;
+        history.push(b"defg").await;
+        let read = history.read_since(0).await;
+        assert_eq!(read.lost_chunks, 0);
+        assert_eq!(read.dropped, 3);
+    }
+
+    #[tokio::test]
+    async fn push_notifies_the_watchers() {
+        let history = History::new(8);
+        let mut total = history.subscribe();
+        history.push(b"abc").await;
+        assert!(total.has_changed().unwrap());
+        assert_eq!(*total.borrow_and_update(), 3);
+        assert_eq!(&history.snapshot().await[..], b"abc");
+    }
+}
@@ -1,4 +1,12 @@
+mod aggregated;
+mod history;
+mod patterns;
+mod port;
+mod prompts;
+mod resources;
+mod sessions;
 mod tools;
+mod transact;
 
 use axum::Router;
 use rmcp::transport::{
@@ -9,10 +17,16 @@ use tokio::signal;
 use tokio::sync::oneshot;
 use tower_http::cors::CorsLayer;
 
-use pza_serial_port_client::SERVER_TYPE_NAME;
+use crate::Topics;
+use crate::MCP_ALL_PORTS_ENDPOINT;
+use crate::SERVER_TYPE_NAME;
+use aggregated::PortsService;
+use port::Port;
+use std::collections::BTreeMap;
 use tools::PowerSupplyService;
 
 use crate::server::config::ServerConfig;
+use crate::server::drivers::Factory as DriverFactory;
 
 pub struct McpService {}
 
@@ -25,7 +39,7 @@ impl McpService {
 
     /// Starts the server with the given service
     ///
-    pub async fn start(config: ServerConfig) -> anyhow::Result<()> {
+    pub async fn start(config: ServerConfig, factory: &DriverFactory) -> anyhow::Result<()> {
         // Bind and serve the application
         let bind_address = format!("{}:{}", config.mcp.host, config.mcp.port);
         let listener = TcpListener::bind(&bind_address).await?;
@@ -35,28 +49,50 @@ impl McpService {
 
         let psu_names = config.runner_names();
 
-        //
+        // Ports are shared by the instance endpoints and the aggregated one
+        let mut ports = BTreeMap::new();
         for psu_name in psu_names {
-            let service_tools = PowerSupplyService::new(config.clone(), psu_name.clone()).await?;
+            ports.insert(psu_name.clone(), Port::new(&config, factory, psu_name)?);
+        }
+
+        //
+        for (psu_name, port) in &ports {
+            let port = port.clone();
 
-            // Create the streamable HTTP service for MCP protocol handling
+            // Create the streamable HTTP service for MCP protocol handling,
+            // each session gets its own service: resource subscriptions and
+            // read positions are not shared between sessions
             let mcp_service = StreamableHttpService::new(
-                move || Ok(service_tools.clone()),
+                move || Ok(PowerSupplyService::new(port.clone())),
                 LocalSessionManager::default().into(),
                 Default::default(),
             );
 
-            // MCP endpoint - using streamable_http_server for MCP protocol handling
-            app = app.nest_service(
-                format!("/{}/{}", SERVER_TYPE_NAME, &psu_name).as_str(),
-                mcp_service,
-            );
+            // MCP endpoint - same path as the MQTT topic prefix of the instance
+            let endpoint = format!("/{}", Topics::new(psu_name).prefix);
+            app = app.nest_service(endpoint.as_str(), mcp_service);
 
             // Log the endpoint
             tracing::info!(
                 "MCP server listening on http://{}{}",
                 bind_address,
-                format!("/{}/{}", SERVER_TYPE_NAME, &psu_name)
+                endpoint
+            );
+        }
+
+        // Aggregated endpoint, every port with an instance parameter
+        {
+            let mcp_service = StreamableHttpService::new(
+                move || Ok(PortsService::new(&ports)),
+                LocalSessionManager::default().into(),
+                Default::default(),
+            );
+            let endpoint = format!("/{}/{}", SERVER_TYPE_NAME, MCP_ALL_PORTS_ENDPOINT);
+            app = app.nest_service(endpoint.as_str(), mcp_service);
+            tracing::info!(
+                "MCP server (all ports) listening on http://{}{}",
+                bind_address,
+                endpoint
             );
         }
 
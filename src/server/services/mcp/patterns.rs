@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use regex::bytes::Regex;
+use rmcp::model::*;
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::port::Port;
+
+/// Timeout of wait_for_patterns when not given
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// Period of the buffer checks while waiting
+const POLL_PERIOD: Duration = Duration::from_millis(50);
+
+/// How a pattern is matched against the received data
+#[derive(Serialize, Deserialize, JsonSchema, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternKind {
+    /// Exact text
+    #[default]
+    Literal,
+    /// Regular expression, capture groups are reported
+    Regex,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WaitPattern {
+    /// Label reported when this pattern matches (e.g., "ok", "error", "login prompt")
+    label: String,
+    /// Text or regular expression to look for
+    pattern: String,
+    /// "literal" (default) or "regex"
+    #[serde(default)]
+    kind: PatternKind,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WaitForPatternsParams {
+    /// Patterns to wait for, the earliest match in the received data wins
+    patterns: Vec<WaitPattern>,
+    /// Timeout in milliseconds (defaults to 5000ms)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
+    /// Whether to remove the data up to the end of the match from the buffer (defaults to true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clear_buffer: Option<bool>,
+    /// Cursor returned by a read, to also match the data received since
+    /// (defaults to the data received after the call)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<u64>,
+}
+
+/// Result of a successful wait, returned as JSON
+#[derive(Serialize)]
+struct PatternMatch {
+    /// Label of the matching pattern
+    label: String,
+    /// Text received before the match
+    before: String,
+    /// Matched text
+    matched: String,
+    /// Capture groups by index, group 0 excluded, null if not participating
+    captures: Vec<Option<String>>,
+    /// Named capture groups
+    named: BTreeMap<String, String>,
+    /// Time waited in milliseconds
+    elapsed_ms: u128,
+    /// Cursor following the match, to pass to the next wait or read
+    cursor: u64,
+}
+
+/// Compiled pattern with its label
+struct CompiledPattern {
+    label: String,
+    regex: Regex,
+}
+
+/// Find the earliest match of the patterns, the first listed wins on a tie
+///
+/// Returns the match and the offset of its end in the data.
+fn find_earliest(patterns: &[CompiledPattern], data: &[u8]) -> Option<(PatternMatch, usize)> {
+    let (pattern, captures) = patterns
+        .iter()
+        .filter_map(|pattern| pattern.regex.captures(data).map(|c| (pattern, c)))
+        .min_by_key(|(_, captures)| captures.get(0).map(|m| m.start()))?;
+    let whole = captures.get(0)?;
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+
+    let named = pattern
+        .regex
+        .capture_names()
+        .flatten()
+        .filter_map(|name| {
+            captures
+                .name(name)
+                .map(|m| (name.to_string(), text(m.as_bytes())))
+        })
+        .collect();
+    let result = PatternMatch {
+        label: pattern.label.clone(),
+        before: text(&data[..whole.start()]),
+        matched: text(whole.as_bytes()),
+        captures: captures
+            .iter()
+            .skip(1)
+            .map(|m| m.map(|m| text(m.as_bytes())))
+            .collect(),
+        named,
+        elapsed_ms: 0,
+        cursor: 0,
+    };
+    Some((result, whole.end()))
+}
+
+impl Port {
+    //--------------------------------------------------------------------------
+
+    /// Wait until one of several labeled patterns is received
+    ///
+    /// Only the data received after the call, or after the given cursor, is
+    /// searched.
+    pub async fn wait_for_patterns(
+        &self,
+        params: WaitForPatternsParams,
+    ) -> Result<CallToolResult, McpError> {
+        if params.patterns.is_empty() {
+            return Err(McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                "At least one pattern is required".to_string(),
+                None,
+            ));
+        }
+        let patterns = params
+            .patterns
+            .iter()
+            .map(|pattern| {
+                let expression = match pattern.kind {
+                    PatternKind::Literal => regex::escape(&pattern.pattern),
+                    PatternKind::Regex => pattern.pattern.clone(),
+                };
+                Regex::new(&expression)
+                    .map(|regex| CompiledPattern {
+                        label: pattern.label.clone(),
+                        regex,
+                    })
+                    .map_err(|e| {
+                        McpError::new(
+                            ErrorCode::INVALID_PARAMS,
+                            format!("Invalid pattern '{}': {}", pattern.label, e),
+                            None,
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let timeout_ms = params.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+        let clear_buffer = params.clear_buffer.unwrap_or(true);
+        let start = self.wait_start(params.cursor);
+
+        let start_time = Instant::now();
+        let timeout_duration = Duration::from_millis(timeout_ms);
+
+        loop {
+            {
+                let pending = self.rx_history().read_since(start).await;
+                if let Some((mut found, end)) = find_earliest(&patterns, &pending.data) {
+                    found.cursor = pending.start + end as u64;
+                    if clear_buffer {
+                        self.consume_until(found.cursor).await;
+                    }
+                    found.elapsed_ms = start_time.elapsed().as_millis();
+
+                    info!(
+                        "Pattern '{}' matched on '{}' after {}ms",
+                        found.label,
+                        self.name(),
+                        found.elapsed_ms
+                    );
+                    let json = serde_json::to_string_pretty(&found).unwrap_or_default();
+                    return Ok(CallToolResult::success(vec![Content::text(json)]));
+                }
+            }
+
+            if start_time.elapsed() > timeout_duration {
+                let labels: Vec<_> = patterns.iter().map(|p| p.label.as_str()).collect();
+                return Ok(CallToolResult::success(vec![Content::text(format!(
+                    "Timeout: none of the patterns {:?} matched within {}ms",
+                    labels, timeout_ms
+                ))]));
+            }
+
+            tokio::time::sleep(POLL_PERIOD).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(patterns: &[(&str, &str)]) -> Vec<CompiledPattern> {
+        patterns
+            .iter()
+            .map(|(label, expression)| CompiledPattern {
+                label: label.to_string(),
+                regex: Regex::new(expression).unwrap(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn no_match_returns_none() {
+        let patterns = compile(&[("ok", "OK"), ("error", "ERROR")]);
+        assert!(find_earliest(&patterns, b"AT\r\n").is_none());
+    }
+
+    #[test]
+    fn earliest_match_wins_over_list_order() {
+        let patterns = compile(&[("ok", "OK"), ("error", "ERROR")]);
+        let (found, end) = find_earliest(&patterns, b"AT\r\nERROR\r\nOK\r\n").unwrap();
+        assert_eq!(found.label, "error");
+        assert_eq!(found.before, "AT\r\n");
+        assert_eq!(found.matched, "ERROR");
+        assert_eq!(end, 9);
+    }
+
+    #[test]
+    fn first_listed_wins_on_a_tie() {
+        let patterns = compile(&[("prompt", r"\$"), ("root", r"\$ ")]);
+        let (found, _) = find_earliest(&patterns, b"user $ ").unwrap();
+        assert_eq!(found.label, "prompt");
+    }
+
+    #[test]
+    fn captures_are_reported() {
+        let patterns = compile(&[("csq", r"\+CSQ: (?P<rssi>\d+),(\d+)(x)?")]);
+        let (found, end) = find_earliest(&patterns, b"+CSQ: 21,99\r\nOK").unwrap();
+        assert_eq!(found.matched, "+CSQ: 21,99");
+        assert_eq!(
+            found.captures,
+            vec![Some("21".to_string()), Some("99".to_string()), None]
+        );
+        assert_eq!(found.named.get("rssi").map(String::as_str), Some("21"));
+        assert_eq!(end, 11);
+    }
+}
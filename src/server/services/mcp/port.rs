@@ -0,0 +1,729 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use rmcp::model::*;
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use tracing::info;
+
+use super::history::History;
+use super::history::HistoryRead;
+use super::sessions::Sessions;
+use crate::payload::FlowControl;
+use crate::payload::InstanceInfo;
+use crate::payload::LineSettingsPayload;
+use crate::payload::Parity;
+use crate::payload::StopBits;
+use crate::SerialPortClient;
+
+use crate::server::config::ServerConfig;
+use crate::server::drivers::Factory as DriverFactory;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SendBytesParams {
+    /// Data to send, encoded as hexadecimal string (e.g., "48656c6c6f" for "Hello")
+    data: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SendTextParams {
+    /// Text data to send to the serial port
+    text: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReadBytesParams {
+    /// Maximum number of bytes to read (optional, defaults to all available data)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_bytes: Option<usize>,
+    /// Whether to clear the buffer after reading (defaults to false)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clear_buffer: Option<bool>,
+    /// Cursor returned by a previous read, to read everything received since
+    /// (defaults to the start of the buffer)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ReadTextParams {
+    /// Maximum number of characters to read (optional, defaults to all available data)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_chars: Option<usize>,
+    /// Whether to clear the buffer after reading (defaults to false)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clear_buffer: Option<bool>,
+    /// Cursor returned by a previous read, to read everything received since
+    /// (defaults to the start of the buffer)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct WaitForTextParams {
+    /// The text to wait for
+    expected_text: String,
+    /// Timeout in milliseconds (defaults to 5000ms)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
+    /// Whether to clear the buffer after finding the text (defaults to true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clear_buffer: Option<bool>,
+    /// Cursor returned by a read, to also match the data received since
+    /// (defaults to the data received after the call)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GetLineSettingsParams {}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SetLineSettingsParams {
+    /// Baud rate (e.g., 115200), unchanged if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baud_rate: Option<u32>,
+    /// Number of data bits (5 to 8), unchanged if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_bits: Option<u8>,
+    /// Parity (none, odd, even), unchanged if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parity: Option<Parity>,
+    /// Stop bits (one, two), unchanged if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_bits: Option<StopBits>,
+    /// Flow control (none, software, hardware), unchanged if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flow_control: Option<FlowControl>,
+}
+
+/// Capacity of the rx channel of the port clients, sized for the tools that
+/// follow the rx data live rather than through the buffer
+const RX_CHANNEL_CAPACITY: usize = 1024;
+
+/// Number of rx bytes kept when the instance does not configure it
+const DEFAULT_RX_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Number of bytes kept in the tx history of the log resources
+const TX_HISTORY_CAPACITY: usize = 64 * 1024;
+
+/// Maximum time to wait for the runner to answer a line settings command
+const LINE_SETTINGS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Serial port instance served by the MCP server
+///
+/// Holds the client of the instance and the history of the data received
+/// since the start of the server, shared by every MCP endpoint. The buffer
+/// read by the tools is the part of the rx history after the read position,
+/// which belongs to each MCP session (see `for_session`).
+#[derive(Clone)]
+pub struct Port {
+    /// Name of the instance
+    name: String,
+
+    /// Description of the instance, as published in the registry
+    info: InstanceInfo,
+
+    /// Client of the instance
+    client: SerialPortClient,
+
+    /// Cursor of the first rx byte not consumed by the tools yet
+    read_position: Arc<AtomicU64>,
+
+    /// MCP sessions connected to the port
+    sessions: Sessions,
+
+    /// Recent data received from the port, never consumed
+    rx_history: History,
+
+    /// Recent data sent to the port, by any client
+    tx_history: History,
+}
+
+impl Port {
+    //--------------------------------------------------------------------------
+
+    /// Connect to an instance and start buffering its rx data
+    pub fn new(
+        config: &ServerConfig,
+        factory: &DriverFactory,
+        name: String,
+    ) -> anyhow::Result<Self> {
+        let client = SerialPortClient::builder()
+            .with_ip(config.broker_endpoint())
+            .with_power_supply_name(name.clone())
+            .with_channel_capacity(RX_CHANNEL_CAPACITY)
+            .enable_tx_monitoring(true)
+            .build()?;
+        debug!("Client initialized");
+
+        let runner_config = config
+            .runners
+            .as_ref()
+            .and_then(|runners| runners.get(&name))
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No runner named '{}' in configuration", name))?;
+
+        let rx_history = History::new(
+            runner_config
+                .mcp_buffer_size
+                .unwrap_or(DEFAULT_RX_BUFFER_SIZE),
+        );
+        let tx_history = History::new(TX_HISTORY_CAPACITY);
+
+        // Spawn a task to listen for incoming data from the rx channel
+        let rx_client = client.clone();
+        let rx_data_history = rx_history.clone();
+        tokio::spawn(async move {
+            let mut rx_channel = rx_client.subscribe_rx();
+
+            // Chunks skipped by the channel, recorded with the data following them
+            let mut lost_chunks = 0;
+            loop {
+                match rx_channel.recv().await {
+                    Ok(data) => {
+                        rx_data_history.push_after_loss(&data, lost_chunks).await;
+                        lost_chunks = 0;
+                        debug!(
+                            "Received {} bytes, total received: {}",
+                            data.len(),
+                            rx_data_history.end()
+                        );
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(count)) => {
+                        tracing::warn!("Rx history lagged, {} chunks lost", count);
+                        lost_chunks += count;
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
+        // Keep the data sent to the port for the tx log
+        let tx_client = client.clone();
+        let tx_data_history = tx_history.clone();
+        tokio::spawn(async move {
+            let mut tx_channel = tx_client.subscribe_tx();
+            loop {
+                match tx_channel.recv().await {
+                    Ok(data) => tx_data_history.push(&data).await,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
+        Ok(Self {
+            info: factory.instance_info(&name, &runner_config),
+            name,
+            client,
+            read_position: Arc::new(AtomicU64::new(0)),
+            sessions: Sessions::default(),
+            rx_history,
+            tx_history,
+        })
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Name of the instance
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Client of the instance
+    pub fn client(&self) -> &SerialPortClient {
+        &self.client
+    }
+
+    /// Data received from the port, not consumed yet
+    pub async fn pending_data(&self) -> HistoryRead {
+        let position = self.read_position.load(Ordering::SeqCst);
+        self.rx_history.read_since(position).await
+    }
+
+    /// Mark the rx data up to a cursor as consumed
+    pub async fn consume_until(&self, cursor: u64) {
+        self.read_position.fetch_max(cursor, Ordering::SeqCst);
+    }
+
+    /// Data received from a cursor, or the data not consumed yet
+    async fn read_from(&self, cursor: Option<u64>) -> HistoryRead {
+        match cursor {
+            Some(cursor) => self.rx_history.read_since(cursor).await,
+            None => self.pending_data().await,
+        }
+    }
+
+    /// Cursor the wait tools start matching from
+    ///
+    /// The data already received is left to the read tools: without an
+    /// explicit cursor, only the data received after the call is matched.
+    pub fn wait_start(&self, cursor: Option<u64>) -> u64 {
+        cursor.unwrap_or_else(|| self.rx_history.end())
+    }
+
+    /// Cursor of the first rx byte not consumed by the tools yet
+    pub fn read_position(&self) -> &Arc<AtomicU64> {
+        &self.read_position
+    }
+
+    /// MCP sessions connected to the port
+    pub fn sessions(&self) -> &Sessions {
+        &self.sessions
+    }
+
+    /// Copy of the port for an MCP session
+    ///
+    /// The copy shares the client and the histories, but has its own read
+    /// position, starting at the oldest data kept: a `clear_buffer` in one
+    /// session does not consume the data another one is waiting for.
+    pub fn for_session(&self) -> Port {
+        Port {
+            read_position: Arc::new(AtomicU64::new(self.rx_history.first())),
+            ..self.clone()
+        }
+    }
+
+    /// Recent data received from the port
+    pub fn rx_history(&self) -> &History {
+        &self.rx_history
+    }
+
+    /// Recent data sent to the port
+    pub fn tx_history(&self) -> &History {
+        &self.tx_history
+    }
+
+    /// Description of the instance with its last known status and presence
+    pub fn info(&self) -> InstanceInfo {
+        InstanceInfo {
+            status: self.client.status().map(|status| status.status),
+            presence: self.client.presence(),
+            ..self.info.clone()
+        }
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Send hex encoded bytes to the serial port
+    pub async fn send_byte_data(
+        &self,
+        params: SendBytesParams,
+    ) -> Result<CallToolResult, McpError> {
+        let hex_data = &params.data;
+
+        // Convert hex string to bytes
+        let bytes_data = hex::decode(hex_data).map_err(|e| {
+            McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Invalid hex data: {}", e),
+                None,
+            )
+        })?;
+
+        // Convert to bytes::Bytes and send via the client
+        let bytes_to_send = bytes::Bytes::from(bytes_data);
+
+        // Send the data via the SerialPortClient
+        self.client.send(bytes_to_send.clone()).await.map_err(|e| {
+            McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Failed to send data to serial port: {}", e),
+                None,
+            )
+        })?;
+
+        info!(
+            "Successfully sent {} bytes to serial port '{}'",
+            bytes_to_send.len(),
+            self.name
+        );
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Sent {} bytes to serial port: {}",
+            bytes_to_send.len(),
+            hex_data
+        ))]))
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Send UTF-8 text to the serial port
+    pub async fn send_text_data(&self, params: SendTextParams) -> Result<CallToolResult, McpError> {
+        let text_data = &params.text;
+
+        // Convert text to bytes using UTF-8 encoding
+        let bytes_to_send = bytes::Bytes::from(text_data.as_bytes().to_vec());
+
+        // Send the data via the SerialPortClient
+        self.client.send(bytes_to_send.clone()).await.map_err(|e| {
+            McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Failed to send text to serial port: {}", e),
+                None,
+            )
+        })?;
+
+        info!(
+            "Successfully sent {} bytes of text to serial port '{}': '{}'",
+            bytes_to_send.len(),
+            self.name,
+            text_data
+        );
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Sent {} bytes of text to serial port: '{}'",
+            bytes_to_send.len(),
+            text_data
+        ))]))
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Read the buffered data as an hex string
+    pub async fn read_byte_data(
+        &self,
+        params: ReadBytesParams,
+    ) -> Result<CallToolResult, McpError> {
+        let max_bytes = params.max_bytes.unwrap_or(usize::MAX);
+        let clear_buffer = params.clear_buffer.unwrap_or(false);
+
+        let read = self.read_from(params.cursor).await;
+
+        // Determine how many bytes to read
+        let bytes_to_read = std::cmp::min(max_bytes, read.data.len());
+        let data_bytes = read.data.slice(..bytes_to_read);
+        let cursor = read.start + bytes_to_read as u64;
+
+        if clear_buffer {
+            self.consume_until(cursor).await;
+        }
+
+        if bytes_to_read == 0 {
+            return Ok(CallToolResult::success(vec![Content::text(read_footer(
+                "No data available in buffer".to_string(),
+                cursor,
+                &read,
+            ))]));
+        }
+
+        // Convert to hex string
+        let hex_data = hex::encode(&data_bytes);
+
+        info!(
+            "Read {} bytes from serial port buffer (hex: {})",
+            bytes_to_read, hex_data
+        );
+
+        Ok(CallToolResult::success(vec![Content::text(read_footer(
+            format!(
+                "Read {} bytes from serial port buffer:\nHex: {}\nText (if UTF-8): {}",
+                bytes_to_read,
+                hex_data,
+                String::from_utf8_lossy(&data_bytes)
+            ),
+            cursor,
+            &read,
+        ))]))
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Read the buffered data as text
+    pub async fn read_text_data(&self, params: ReadTextParams) -> Result<CallToolResult, McpError> {
+        let max_chars = params.max_chars.unwrap_or(usize::MAX);
+        let clear_buffer = params.clear_buffer.unwrap_or(false);
+
+        let read = self.read_from(params.cursor).await;
+
+        // Whole characters only, so the next read starts on a character
+        let bytes_consumed = text_prefix_len(&read.data, max_chars);
+        let text_to_read = String::from_utf8_lossy(&read.data[..bytes_consumed]).to_string();
+        let cursor = read.start + bytes_consumed as u64;
+
+        if clear_buffer {
+            self.consume_until(cursor).await;
+        }
+
+        if text_to_read.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(read_footer(
+                "No data available in buffer".to_string(),
+                cursor,
+                &read,
+            ))]));
+        }
+
+        info!(
+            "Read {} characters ({} bytes) from serial port buffer as text",
+            text_to_read.chars().count(),
+            bytes_consumed
+        );
+
+        Ok(CallToolResult::success(vec![Content::text(read_footer(
+            format!(
+                "Read {} characters from serial port buffer:\n{}",
+                text_to_read.chars().count(),
+                text_to_read
+            ),
+            cursor,
+            &read,
+        ))]))
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Wait for a text to be received within a timeout
+    ///
+    /// Only the data received after the call, or after the given cursor, is
+    /// searched.
+    pub async fn wait_for_text(
+        &self,
+        params: WaitForTextParams,
+    ) -> Result<CallToolResult, McpError> {
+        let expected_text = &params.expected_text;
+        let timeout_ms = params.timeout_ms.unwrap_or(5000);
+        let clear_buffer = params.clear_buffer.unwrap_or(true);
+        let start = self.wait_start(params.cursor);
+
+        let start_time = std::time::Instant::now();
+        let timeout_duration = std::time::Duration::from_millis(timeout_ms);
+
+        loop {
+            // Check if timeout has been reached
+            if start_time.elapsed() > timeout_duration {
+                return Ok(CallToolResult::success(vec![Content::text(format!(
+                    "Timeout: Expected text '{}' not found within {}ms",
+                    expected_text, timeout_ms
+                ))]));
+            }
+
+            // Check current buffer content
+            {
+                let pending = self.rx_history.read_since(start).await;
+                let found = pending
+                    .data
+                    .windows(expected_text.len().max(1))
+                    .position(|window| window == expected_text.as_bytes());
+
+                if let Some(pos) = found {
+                    // Found the expected text
+                    let cursor = pending.start + (pos + expected_text.len()) as u64;
+                    let result_text = if clear_buffer {
+                        // Clear everything up to and including the expected text
+                        self.consume_until(cursor).await;
+                        format!(
+                            "Found expected text '{}' at position {} (buffer cleared)",
+                            expected_text, pos
+                        )
+                    } else {
+                        format!(
+                            "Found expected text '{}' at position {}",
+                            expected_text, pos
+                        )
+                    };
+
+                    info!(
+                        "Found expected text '{}' after {:?}",
+                        expected_text,
+                        start_time.elapsed()
+                    );
+
+                    return Ok(CallToolResult::success(vec![Content::text(read_footer(
+                        result_text,
+                        cursor,
+                        &pending,
+                    ))]));
+                }
+            }
+
+            // Small delay before checking again
+            tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        }
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Read the line settings applied by the driver
+    pub async fn get_line_settings(
+        &self,
+        _params: GetLineSettingsParams,
+    ) -> Result<CallToolResult, McpError> {
+        let settings = self
+            .client
+            .line_settings(LINE_SETTINGS_TIMEOUT)
+            .await
+            .map_err(|e| {
+                McpError::new(
+                    ErrorCode::INTERNAL_ERROR,
+                    format!("Failed to read line settings: {}", e),
+                    None,
+                )
+            })?;
+        line_settings_result("Line settings", &settings)
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Change the given line settings through the driver
+    pub async fn set_line_settings(
+        &self,
+        params: SetLineSettingsParams,
+    ) -> Result<CallToolResult, McpError> {
+        let changes = LineSettingsPayload {
+            baud_rate: params.baud_rate,
+            data_bits: params.data_bits,
+            parity: params.parity,
+            stop_bits: params.stop_bits,
+            flow_control: params.flow_control,
+            ..LineSettingsPayload::request()
+        };
+        if changes.is_read_only() {
+            return Err(McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                "No line setting to change".to_string(),
+                None,
+            ));
+        }
+
+        let settings = self
+            .client
+            .set_line_settings(changes, LINE_SETTINGS_TIMEOUT)
+            .await
+            .map_err(|e| {
+                McpError::new(
+                    ErrorCode::INTERNAL_ERROR,
+                    format!("Failed to change line settings: {}", e),
+                    None,
+                )
+            })?;
+
+        info!(
+            "Changed line settings of serial port '{}': {:?}",
+            self.name, settings
+        );
+        line_settings_result("Applied line settings", &settings)
+    }
+}
+
+/// Format line settings as a tool result
+fn line_settings_result(
+    title: &str,
+    settings: &LineSettingsPayload,
+) -> Result<CallToolResult, McpError> {
+    let json = serde_json::json!({
+        "baud_rate": settings.baud_rate,
+        "data_bits": settings.data_bits,
+        "parity": settings.parity,
+        "stop_bits": settings.stop_bits,
+        "flow_control": settings.flow_control,
+    });
+    Ok(CallToolResult::success(vec![Content::text(format!(
+        "{}:\n{}",
+        title,
+        serde_json::to_string_pretty(&json).unwrap_or_default()
+    ))]))
+}
+
+/// Length in bytes of the first `max_chars` characters of the data
+///
+/// An incomplete character at the end is left out, it may be completed by
+/// the next chunk; invalid bytes count as one character each.
+fn text_prefix_len(data: &[u8], max_chars: usize) -> usize {
+    let mut len = 0;
+    let mut chars = 0;
+    while chars < max_chars && len < data.len() {
+        let (valid, invalid) = match std::str::from_utf8(&data[len..]) {
+            Ok(text) => (text, None),
+            Err(e) => (
+                std::str::from_utf8(&data[len..len + e.valid_up_to()]).unwrap_or_default(),
+                e.error_len(),
+            ),
+        };
+        if let Some((index, _)) = valid.char_indices().nth(max_chars - chars) {
+            return len + index;
+        }
+        chars += valid.chars().count();
+        len += valid.len();
+        match invalid {
+            // Invalid bytes are shown as one replacement character
+            Some(invalid) if chars < max_chars => {
+                len += invalid;
+                chars += 1;
+            }
+            // End of the data, or an incomplete character at the end
+            _ => break,
+        }
+    }
+    len
+}
+
+/// Append the cursor of the next read, and warnings about dropped or lost data
+fn read_footer(mut text: String, cursor: u64, read: &HistoryRead) -> String {
+    text.push_str(&format!("\nCursor: {}", cursor));
+    if read.dropped > 0 {
+        text.push_str(&format!(
+            "\nWarning: {} bytes were dropped before this data, the buffer overflowed",
+            read.dropped
+        ));
+    }
+    if read.lost_chunks > 0 {
+        text.push_str(&format!(
+            "\nWarning: {} received chunks were lost, the data is incomplete",
+            read.lost_chunks
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_prefix_len_counts_characters() {
+        assert_eq!(text_prefix_len(b"hello", 3), 3);
+        assert_eq!(text_prefix_len(b"hello", 10), 5);
+        assert_eq!(text_prefix_len("héllo".as_bytes(), 2), 3);
+        assert_eq!(text_prefix_len(b"", 4), 0);
+    }
+
+    #[test]
+    fn text_prefix_len_leaves_out_an_incomplete_character() {
+        let data = "aé".as_bytes();
+        assert_eq!(text_prefix_len(&data[..2], 5), 1);
+    }
+
+    #[test]
+    fn text_prefix_len_counts_invalid_bytes_as_one_character() {
+        assert_eq!(text_prefix_len(b"a\xFFb", 2), 2);
+        assert_eq!(text_prefix_len(b"a\xFFb", 3), 3);
+        assert_eq!(text_prefix_len(b"\xFF\xFE", 1), 1);
+    }
+
+    #[test]
+    fn read_footer_gives_the_cursor() {
+        let read = HistoryRead {
+            data: bytes::Bytes::from_static(b"abc"),
+            start: 10,
+            dropped: 0,
+            lost_chunks: 0,
+        };
+        assert_eq!(read_footer("abc".to_string(), 13, &read), "abc\nCursor: 13");
+    }
+
+    #[test]
+    fn read_footer_warns_about_dropped_data() {
+        let read = HistoryRead {
+            data: bytes::Bytes::from_static(b"abc"),
+            start: 10,
+            dropped: 4,
+            lost_chunks: 2,
+        };
+        let text = read_footer(String::new(), 13, &read);
+        assert!(text.contains("Cursor: 13"));
+        assert!(text.contains("4 bytes were dropped"));
+        assert!(text.contains("2 received chunks were lost"));
+    }
+}
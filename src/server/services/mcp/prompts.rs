@@ -0,0 +1,174 @@
+use rmcp::model::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::port::Port;
+
+/// Number of recent rx bytes pre-filled in the prompts
+const PROMPT_LOG_BYTES: usize = 8 * 1024;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct BootLogPromptParams {
+    /// Area to focus on (e.g., "network", "storage", "kernel panic"), optional
+    #[serde(skip_serializing_if = "Option::is_none")]
+    focus: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct LinuxLoginPromptParams {
+    /// User name to log in with (defaults to "root")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    /// Password of the user, optional
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AtModemPromptParams {
+    /// What the session should achieve (e.g., "check the signal quality"), optional
+    #[serde(skip_serializing_if = "Option::is_none")]
+    goal: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct IdentifyDevicePromptParams {
+    /// What is already known about the device (e.g., "an ESP32 board"), optional
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+}
+
+/// Build a prompt made of a single user message
+fn user_prompt(description: String, text: String) -> GetPromptResult {
+    GetPromptResult {
+        description: Some(description),
+        messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+    }
+}
+
+impl Port {
+    //--------------------------------------------------------------------------
+
+    /// Last bytes received from the port, as text for the prompts
+    async fn recent_console(&self) -> String {
+        let data = self.rx_history().snapshot().await;
+        let start = data.len().saturating_sub(PROMPT_LOG_BYTES);
+        let text = String::from_utf8_lossy(&data[start..]);
+        if text.trim().is_empty() {
+            "(nothing received yet)".to_string()
+        } else {
+            format!("```\n{}\n```", text)
+        }
+    }
+
+    //--------------------------------------------------------------------------
+
+    /// Prompt to review the boot log of the device
+    pub async fn boot_log_prompt(&self, params: BootLogPromptParams) -> GetPromptResult {
+        let focus = params
+            .focus
+            .map(|focus| format!("\nPay particular attention to: {}.", focus))
+            .unwrap_or_default();
+        user_prompt(
+            format!(
+                "Inspect the boot log of serial port '{}' for errors",
+                self.name()
+            ),
+            format!(
+                r#"Inspect the boot log of the device on serial port "{name}" for errors.{focus}
+
+Recent data received from the port:
+{console}
+
+1. If the log above does not cover a full boot, call read_text_data and wait_for_patterns to collect more, or ask the user to reboot the device.
+2. List every error, warning, failed service, timeout or kernel oops, quoting the exact lines.
+3. For each problem, explain the likely cause and suggest a fix or the next command to run.
+4. Finish with a short verdict: did the device boot correctly?"#,
+                name = self.name(),
+                focus = focus,
+                console = self.recent_console().await
+            ),
+        )
+    }
+
+    /// Prompt to log in to a Linux shell on the port
+    pub async fn linux_login_prompt(&self, params: LinuxLoginPromptParams) -> GetPromptResult {
+        let username = params.username.unwrap_or_else(|| "root".to_string());
+        let password = params
+            .password
+            .map(|password| format!("the password \"{}\"", password))
+            .unwrap_or_else(|| "no password (ask the user if one is requested)".to_string());
+        user_prompt(
+            format!("Log in to a Linux shell on serial port '{}'", self.name()),
+            format!(
+                r#"Log in to the Linux shell of the device on serial port "{name}" as "{username}", with {password}.
+
+Recent data received from the port:
+{console}
+
+1. Work out the current state from the data above: login prompt, password prompt, shell prompt or nothing.
+2. If nothing is shown, send a newline with send_and_wait to get a prompt.
+3. Answer the "login:" and "Password:" prompts with send_and_wait, using wait_for_patterns with labels like "shell", "password", "login" and "incorrect" to follow the session.
+4. Once a shell prompt is received, confirm with `whoami` and report the prompt to the user.
+Never retry a rejected password more than once, report the failure instead."#,
+                name = self.name(),
+                username = username,
+                password = password,
+                console = self.recent_console().await
+            ),
+        )
+    }
+
+    /// Prompt to drive an AT command session with a modem
+    pub async fn at_modem_prompt(&self, params: AtModemPromptParams) -> GetPromptResult {
+        let goal = params
+            .goal
+            .unwrap_or_else(|| "identify the modem and report its status".to_string());
+        user_prompt(
+            format!("Drive an AT modem session on serial port '{}'", self.name()),
+            format!(
+                r#"Drive an AT command session with the modem on serial port "{name}". Goal: {goal}.
+
+Recent data received from the port:
+{console}
+
+1. Send "AT\r" with send_and_wait (until_regex "OK|ERROR") to check the modem answers; if not, check the line settings with get_line_settings.
+2. Send one command at a time, each terminated by "\r", and wait for the final "OK" or "ERROR" (or "+CME ERROR: ...") before the next one.
+3. Useful commands: ATI (identification), AT+CGMI/AT+CGMM/AT+CGMR (maker, model, firmware), AT+CPIN? (SIM), AT+CSQ (signal), AT+CREG?/AT+CEREG? (registration), AT+COPS? (operator).
+4. Never send commands that change persistent settings (AT&W, AT+CFUN, AT+COPS=...) without asking the user first.
+5. Summarize the results in plain words, decoding the numeric values (e.g., AT+CSQ to dBm)."#,
+                name = self.name(),
+                goal = goal,
+                console = self.recent_console().await
+            ),
+        )
+    }
+
+    /// Prompt to identify the device connected to the port
+    pub async fn identify_device_prompt(
+        &self,
+        params: IdentifyDevicePromptParams,
+    ) -> GetPromptResult {
+        let hint = params
+            .hint
+            .map(|hint| format!("\nWhat is already known: {}.", hint))
+            .unwrap_or_default();
+        user_prompt(
+            format!("Identify the device on serial port '{}'", self.name()),
+            format!(
+                r#"Identify the unknown device connected to serial port "{name}".{hint}
+
+Recent data received from the port:
+{console}
+
+1. Check the line settings with get_line_settings. If the data above looks garbled, try the common baud rates (115200, 9600, 57600, 38400, 19200) with set_line_settings.
+2. Look for clues in the data received: boot banners, bootloader names (U-Boot, ESP-ROM, ...), kernel versions, shell or login prompts.
+3. If the port is silent, probe with harmless inputs using send_and_wait: a newline, "AT\r", "help\r", "?\r". Never send commands that write, erase or reset anything.
+4. Report the most likely device type, its firmware or OS, the working line settings and how to interact with it, with your confidence level."#,
+                name = self.name(),
+                hint = hint,
+                console = self.recent_console().await
+            ),
+        )
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::model::*;
+use rmcp::service::Peer;
+use rmcp::ErrorData as McpError;
+use rmcp::RoleServer;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use super::history::History;
+use super::port::Port;
+
+/// Scheme of the resource URIs, `serial://{name}/log`
+const URI_SCHEME: &str = "serial://";
+
+/// Minimum delay between two update notifications of the same resource
+const NOTIFY_MIN_PERIOD: Duration = Duration::from_millis(200);
+
+/// Log resources of a port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogKind {
+    /// Data received from the port
+    Rx,
+    /// Data sent to the port
+    Tx,
+}
+
+impl LogKind {
+    /// Last segment of the resource URI
+    fn path(&self) -> &'static str {
+        match self {
+            LogKind::Rx => "log",
+            LogKind::Tx => "tx",
+        }
+    }
+
+    /// History of the port for this log
+    fn history<'a>(&self, port: &'a Port) -> &'a History {
+        match self {
+            LogKind::Rx => port.rx_history(),
+            LogKind::Tx => port.tx_history(),
+        }
+    }
+}
+
+/// URI of a log resource
+pub fn uri(name: &str, kind: LogKind) -> String {
+    format!("{}{}/{}", URI_SCHEME, name, kind.path())
+}
+
+/// Split a resource URI into the port name and the log kind
+pub fn parse_uri(uri: &str) -> Option<(&str, LogKind)> {
+    let (name, path) = uri.strip_prefix(URI_SCHEME)?.rsplit_once('/')?;
+    let kind = match path {
+        "log" => LogKind::Rx,
+        "tx" => LogKind::Tx,
+        _ => return None,
+    };
+    (!name.is_empty()).then_some((name, kind))
+}
+
+/// Log resources of a port
+pub fn list(port: &Port) -> Vec<Resource> {
+    [
+        (
+            LogKind::Rx,
+            "console",
+            "Recent data received from the serial port",
+        ),
+        (LogKind::Tx, "tx", "Recent data sent to the serial port"),
+    ]
+    .into_iter()
+    .map(|(kind, suffix, description)| {
+        let mut resource = RawResource::new(
+            uri(port.name(), kind),
+            format!("{} {}", port.name(), suffix),
+        );
+        resource.description = Some(description.to_string());
+        resource.mime_type = Some("text/plain".to_string());
+        resource.no_annotation()
+    })
+    .collect()
+}
+
+/// Read a log resource of a port
+pub async fn read(port: &Port, kind: LogKind, uri: String) -> ReadResourceResult {
+    let data = kind.history(port).snapshot().await;
+    ReadResourceResult {
+        contents: vec![ResourceContents::text(
+            String::from_utf8_lossy(&data).to_string(),
+            uri,
+        )],
+    }
+}
+
+/// Error returned for an URI that does not match any resource
+pub fn not_found(uri: &str) -> McpError {
+    McpError::resource_not_found(
+        format!("Unknown resource '{}'", uri),
+        Some(serde_json::json!({ "uri": uri })),
+    )
+}
+
+/// Abort a background task when dropped
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Resource subscriptions of an MCP session
+///
+/// Each subscription watches the history of its log and notifies the peer
+/// when new data arrives. The watchers stop on unsubscribe, or once the
+/// session (the last clone of the service) is dropped.
+#[derive(Clone, Default)]
+pub struct Subscriptions {
+    /// Watcher tasks keyed by resource URI
+    watchers: Arc<Mutex<HashMap<String, AbortOnDrop>>>,
+}
+
+impl Subscriptions {
+    //--------------------------------------------------------------------------
+
+    /// Notify the peer of the updates of a log resource
+    pub async fn subscribe(&self, port: &Port, kind: LogKind, uri: String, peer: Peer<RoleServer>) {
+        let mut updates = kind.history(port).subscribe();
+        let notified_uri = uri.clone();
+        let task = tokio::spawn(async move {
+            while updates.changed().await.is_ok() {
+                let param = ResourceUpdatedNotificationParam {
+                    uri: notified_uri.clone(),
+                };
+                if let Err(e) = peer.notify_resource_updated(param).await {
+                    debug!("Stop notifying '{}': {}", notified_uri, e);
+                    break;
+                }
+                // Coalesce the updates of a busy port
+                tokio::time::sleep(NOTIFY_MIN_PERIOD).await;
+            }
+        });
+        self.watchers.lock().await.insert(uri, AbortOnDrop(task));
+    }
+
+    /// Stop notifying the updates of a resource
+    pub async fn unsubscribe(&self, uri: &str) {
+        self.watchers.lock().await.remove(uri);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uri_round_trips() {
+        for kind in [LogKind::Rx, LogKind::Tx] {
+            assert_eq!(parse_uri(&uri("dut", kind)), Some(("dut", kind)));
+        }
+    }
+
+    #[test]
+    fn parse_uri_reads_the_known_paths() {
+        assert_eq!(parse_uri("serial://dut/log"), Some(("dut", LogKind::Rx)));
+        assert_eq!(parse_uri("serial://dut/tx"), Some(("dut", LogKind::Tx)));
+    }
+
+    #[test]
+    fn parse_uri_rejects_invalid_uris() {
+        assert_eq!(parse_uri("file://dut/log"), None);
+        assert_eq!(parse_uri("serial://dut/rx"), None);
+        assert_eq!(parse_uri("serial://dut"), None);
+        assert_eq!(parse_uri("serial:///log"), None);
+        assert_eq!(parse_uri("serial://dut/log/"), None);
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rmcp::model::*;
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::port::Port;
+
+/// Identifier of the next MCP session, unique across the endpoints
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ListSessionsParams {}
+
+/// MCP session registered on a port
+struct SessionEntry {
+    /// Endpoint the session is connected to
+    endpoint: String,
+    /// Name and version of the MCP client, known once initialized
+    client: Option<String>,
+    /// Time of the connection
+    connected_at: Instant,
+    /// Read position of the session on the port
+    read_position: Arc<AtomicU64>,
+}
+
+/// Description of a session connected to a port, returned as JSON
+#[derive(Serialize)]
+struct SessionInfo {
+    /// Identifier of the session
+    id: u64,
+    /// Endpoint the session is connected to
+    endpoint: String,
+    /// Name and version of the MCP client
+    client: Option<String>,
+    /// Time since the connection in seconds
+    connected_s: u64,
+    /// Read position of the session in the rx data
+    read_position: u64,
+    /// Number of bytes received after the read position
+    unread_bytes: u64,
+    /// Whether this is the session calling the tool
+    current: bool,
+}
+
+/// MCP sessions connected to a port
+#[derive(Clone, Default)]
+pub struct Sessions {
+    /// Registered sessions keyed by identifier
+    entries: Arc<Mutex<BTreeMap<u64, SessionEntry>>>,
+}
+
+impl Sessions {
+    //--------------------------------------------------------------------------
+
+    fn insert(&self, id: u64, entry: SessionEntry) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(id, entry);
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(&id);
+        }
+    }
+
+    fn set_client(&self, id: u64, client: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            if let Some(entry) = entries.get_mut(&id) {
+                entry.client = Some(client.to_string());
+            }
+        }
+    }
+
+    /// Describe the sessions, `end` being the cursor of the last rx byte
+    fn list(&self, end: u64, current: u64) -> Vec<SessionInfo> {
+        let Ok(entries) = self.entries.lock() else {
+            return Vec::new();
+        };
+        entries
+            .iter()
+            .map(|(id, entry)| {
+                let read_position = entry.read_position.load(Ordering::SeqCst);
+                SessionInfo {
+                    id: *id,
+                    endpoint: entry.endpoint.clone(),
+                    client: entry.client.clone(),
+                    connected_s: entry.connected_at.elapsed().as_secs(),
+                    read_position,
+                    unread_bytes: end.saturating_sub(read_position),
+                    current: *id == current,
+                }
+            })
+            .collect()
+    }
+}
+
+/// MCP session of an endpoint, registered on the ports it can use
+///
+/// Created with the service of each session, the session is removed from
+/// the ports when the service is dropped, i.e. when the session is closed.
+pub struct Session {
+    /// Identifier of the session
+    id: u64,
+    /// Ports the session is registered on
+    ports: Vec<Port>,
+}
+
+impl Session {
+    //--------------------------------------------------------------------------
+
+    /// Open a session on some ports
+    ///
+    /// Returns the session with the copies of the ports it must use, each
+    /// with the read position of the session.
+    pub fn open(endpoint: &str, ports: impl IntoIterator<Item = Port>) -> Self {
+        let id = NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst);
+        let ports: Vec<Port> = ports
+            .into_iter()
+            .map(|port| {
+                let port = port.for_session();
+                port.sessions().insert(
+                    id,
+                    SessionEntry {
+                        endpoint: endpoint.to_string(),
+                        client: None,
+                        connected_at: Instant::now(),
+                        read_position: port.read_position().clone(),
+                    },
+                );
+                port
+            })
+            .collect();
+        Self { id, ports }
+    }
+
+    /// Identifier of the session
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Copies of the ports for this session
+    pub fn ports(&self) -> &[Port] {
+        &self.ports
+    }
+
+    /// Record the MCP client of the session from its initialize request
+    pub fn set_client(&self, request: &InitializeRequestParam) {
+        let client = format!(
+            "{} {}",
+            request.client_info.name, request.client_info.version
+        );
+        for port in &self.ports {
+            port.sessions().set_client(self.id, &client);
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        for port in &self.ports {
+            port.sessions().remove(self.id);
+        }
+    }
+}
+
+impl Port {
+    //--------------------------------------------------------------------------
+
+    /// List the MCP sessions connected to the port
+    pub async fn list_sessions(
+        &self,
+        _params: ListSessionsParams,
+        current: u64,
+    ) -> Result<CallToolResult, McpError> {
+        let sessions = self.sessions().list(self.rx_history().end(), current);
+        let json = serde_json::to_string_pretty(&sessions).unwrap_or_default();
+        Ok(CallToolResult::success(vec![Content::text(json)]))
+    }
+}
@@ -0,0 +1,281 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use bytes::Bytes;
+use bytes::BytesMut;
+use regex::bytes::Regex;
+use rmcp::model::*;
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::info;
+
+use super::port::Port;
+
+/// Overall timeout of send_and_wait when not given
+const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// Idle timeout of send_and_wait when no stop condition is given
+const DEFAULT_IDLE_MS: u64 = 500;
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SendAndWaitParams {
+    /// Text to send, UTF-8 encoded (give either text or data)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    /// Bytes to send as hexadecimal string (give either text or data)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    /// Stop once this literal text is received
+    #[serde(skip_serializing_if = "Option::is_none")]
+    until_text: Option<String>,
+    /// Stop once this regular expression matches the received data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    until_regex: Option<String>,
+    /// Stop once this number of bytes is received
+    #[serde(skip_serializing_if = "Option::is_none")]
+    byte_count: Option<usize>,
+    /// Stop when nothing is received for this duration in milliseconds
+    /// (defaults to 500ms when no other stop condition is given)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_ms: Option<u64>,
+    /// Overall timeout in milliseconds (defaults to 5000ms)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_ms: Option<u64>,
+}
+
+/// Reason why send_and_wait stopped collecting data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopReason {
+    Text,
+    Regex,
+    ByteCount,
+    Idle,
+    Timeout,
+}
+
+impl StopReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StopReason::Text => "text matched",
+            StopReason::Regex => "regex matched",
+            StopReason::ByteCount => "byte count reached",
+            StopReason::Idle => "idle timeout",
+            StopReason::Timeout => "timeout",
+        }
+    }
+}
+
+/// Stop conditions checked on the data received after the send
+struct StopConditions {
+    text: Option<Vec<u8>>,
+    regex: Option<Regex>,
+    byte_count: Option<usize>,
+}
+
+impl StopConditions {
+    /// Earliest satisfied condition, with the length of data it covers
+    fn check(&self, received: &[u8]) -> Option<(usize, StopReason)> {
+        let text = self.text.as_ref().and_then(|text| {
+            received
+                .windows(text.len().max(1))
+                .position(|window| window == text.as_slice())
+                .map(|pos| (pos + text.len(), StopReason::Text))
+        });
+        let regex = self
+            .regex
+            .as_ref()
+            .and_then(|regex| regex.find(received))
+            .map(|m| (m.end(), StopReason::Regex));
+        let byte_count = self
+            .byte_count
+            .filter(|count| received.len() >= *count)
+            .map(|count| (count, StopReason::ByteCount));
+
+        [text, regex, byte_count]
+            .into_iter()
+            .flatten()
+            .min_by_key(|(end, _)| *end)
+    }
+}
+
+impl Port {
+    //--------------------------------------------------------------------------
+
+    /// Send data, then collect the response until a stop condition is met
+    ///
+    /// The rx data is followed from a subscription taken before the send, so
+    /// the result only holds bytes received after it, whatever the content of
+    /// the shared buffer.
+    pub async fn send_and_wait(
+        &self,
+        params: SendAndWaitParams,
+    ) -> Result<CallToolResult, McpError> {
+        let invalid = |message: String| McpError::new(ErrorCode::INVALID_PARAMS, message, None);
+
+        let to_send = match (&params.text, &params.data) {
+            (Some(text), None) => Bytes::from(text.clone().into_bytes()),
+            (None, Some(data)) => Bytes::from(
+                hex::decode(data).map_err(|e| invalid(format!("Invalid hex data: {}", e)))?,
+            ),
+            _ => return Err(invalid("Give either 'text' or 'data' to send".to_string())),
+        };
+        let conditions = StopConditions {
+            text: params
+                .until_text
+                .filter(|text| !text.is_empty())
+                .map(String::into_bytes),
+            regex: params
+                .until_regex
+                .map(|pattern| Regex::new(&pattern))
+                .transpose()
+                .map_err(|e| invalid(format!("Invalid regex: {}", e)))?,
+            byte_count: params.byte_count.filter(|count| *count > 0),
+        };
+        let no_condition = conditions.text.is_none()
+            && conditions.regex.is_none()
+            && conditions.byte_count.is_none();
+        let idle = params
+            .idle_ms
+            .or(no_condition.then_some(DEFAULT_IDLE_MS))
+            .map(Duration::from_millis);
+        let timeout = Duration::from_millis(params.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+
+        // Mark the position: only the data received from now on is collected
+        let mut rx = self.client().subscribe_rx();
+        self.client().send(to_send.clone()).await.map_err(|e| {
+            McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Failed to send data to serial port: {}", e),
+                None,
+            )
+        })?;
+
+        let start = Instant::now();
+        let deadline = start + timeout;
+        let mut received = BytesMut::new();
+        let mut dropped_chunks = 0u64;
+
+        let reason = loop {
+            if let Some((end, reason)) = conditions.check(&received) {
+                received.truncate(end);
+                break reason;
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break StopReason::Timeout;
+            }
+            let (wait, on_expiry) = match idle {
+                Some(idle) if idle < remaining => (idle, StopReason::Idle),
+                _ => (remaining, StopReason::Timeout),
+            };
+
+            match tokio::time::timeout(wait, rx.recv()).await {
+                Ok(Ok(data)) => received.extend_from_slice(&data),
+                Ok(Err(broadcast::error::RecvError::Lagged(count))) => dropped_chunks += count,
+                Ok(Err(broadcast::error::RecvError::Closed)) => {
+                    return Err(McpError::new(
+                        ErrorCode::INTERNAL_ERROR,
+                        "Rx channel of the serial port closed".to_string(),
+                        None,
+                    ))
+                }
+                Err(_) => break on_expiry,
+            }
+        };
+
+        info!(
+            "send_and_wait on '{}': sent {} bytes, received {} bytes ({})",
+            self.name(),
+            to_send.len(),
+            received.len(),
+            reason.as_str()
+        );
+
+        let mut text = format!(
+            "Sent {} bytes, stopped on {} after {}ms.\nReceived {} bytes after the send:\nText: {}\nHex: {}",
+            to_send.len(),
+            reason.as_str(),
+            start.elapsed().as_millis(),
+            received.len(),
+            String::from_utf8_lossy(&received),
+            hex::encode(&received)
+        );
+        if dropped_chunks > 0 {
+            text.push_str(&format!(
+                "\nWarning: {} received chunks were dropped, the response is incomplete",
+                dropped_chunks
+            ));
+        }
+        Ok(CallToolResult::success(vec![Content::text(text)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop_on(
+        text: Option<&str>,
+        regex: Option<&str>,
+        byte_count: Option<usize>,
+    ) -> StopConditions {
+        StopConditions {
+            text: text.map(|text| text.as_bytes().to_vec()),
+            regex: regex.map(|pattern| Regex::new(pattern).unwrap()),
+            byte_count,
+        }
+    }
+
+    #[test]
+    fn no_condition_never_stops() {
+        assert_eq!(stop_on(None, None, None).check(b"anything"), None);
+    }
+
+    #[test]
+    fn text_stops_after_the_match() {
+        let conditions = stop_on(Some("OK"), None, None);
+        assert_eq!(conditions.check(b"AT\r\nO"), None);
+        assert_eq!(
+            conditions.check(b"AT\r\nOK\r\nmore"),
+            Some((6, StopReason::Text))
+        );
+    }
+
+    #[test]
+    fn regex_stops_after_the_match() {
+        let conditions = stop_on(None, Some(r"\$ $"), None);
+        assert_eq!(conditions.check(b"ls\r\n"), None);
+        assert_eq!(
+            conditions.check(b"ls\r\nfile\r\n$ "),
+            Some((12, StopReason::Regex))
+        );
+    }
+
+    #[test]
+    fn byte_count_stops_at_the_count() {
+        let conditions = stop_on(None, None, Some(4));
+        assert_eq!(conditions.check(b"abc"), None);
+        assert_eq!(
+            conditions.check(b"abcdef"),
+            Some((4, StopReason::ByteCount))
+        );
+    }
+
+    #[test]
+    fn earliest_condition_wins() {
+        let conditions = stop_on(Some("OK"), Some("ERROR"), Some(64));
+        assert_eq!(
+            conditions.check(b"ERROR\r\nOK"),
+            Some((5, StopReason::Regex))
+        );
+        assert_eq!(
+            conditions.check(b"OK\r\nERROR"),
+            Some((2, StopReason::Text))
+        );
+        let conditions = stop_on(Some("OK"), None, Some(3));
+        assert_eq!(conditions.check(b"abcOK"), Some((3, StopReason::ByteCount)));
+    }
+}
@@ -1,5 +1,5 @@
 mod mcp;
-mod runners;
+pub mod runners;
 mod tui;
 use crate::server::cli::Args as CliArgs;
 use crate::server::config::ServerConfig;
@@ -99,18 +99,18 @@ impl Services {
 
         // Start Runners service only if configured
         {
-            let runners_config = self.server_config.runners.clone();
+            let runners_config = self.server_config.started_runners_configs();
             match runners_config {
                 None => {
                     info!("Runners service is disabled in configuration");
                 }
-                Some(_) => {
+                Some(runners_config) => {
                     info!("Starting Runners service...");
-                    let (runners, handle) = RunnersService::start(
-                        self.server_config.clone(),
-                        self.drivers_factory.clone(),
-                    )
-                    .await?;
+                    // Only the runners selected for this process are started
+                    let mut server_config = self.server_config.clone();
+                    server_config.runners = Some(runners_config);
+                    let (runners, handle) =
+                        RunnersService::start(server_config, self.drivers_factory.clone()).await?;
                     self.runners = Some(Arc::new(Mutex::new(runners)));
                     task_monitor
                         .handle_sender()
@@ -120,17 +120,25 @@ impl Services {
             }
         }
 
-        // // Start MCP server only if not disabled
+        // Start MCP server only if not disabled
         {
-            McpService::start(self.server_config.clone()).await?;
-            info!("Started MCP server");
+            if self.server_config.mcp.enable {
+                let factory = self.drivers_factory.lock().await;
+                McpService::start(self.server_config.clone(), &factory).await?;
+                info!("Started MCP server");
+            } else {
+                info!("MCP server is disabled in configuration");
+            }
         }
 
         {
             // Start TUI service only if not disabled
             if self.server_config.tui.enable.unwrap_or(true) {
                 info!("Starting TUI service...");
-                let tui_handle = TuiService::start();
+                let tui_handle = TuiService::start(
+                    self.server_config.runner_names(),
+                    self.server_config.broker_endpoint(),
+                );
                 task_monitor
                     .handle_sender()
                     .send(("tui".to_string(), tui_handle))
@@ -160,6 +168,7 @@ impl Services {
                     info!("Received Ctrl+C signal, shutting down gracefully...");
 
                     // Cancel all running tasks
+                    self.stop_runners().await;
                     task_monitor.cancel_all_monitored_tasks().await;
                     info!("All tasks have been cancelled");
 
@@ -179,6 +188,7 @@ impl Services {
                                     if event_body.task_name == "tui" {
                                         // TUI stopped, shut down other services gracefully
                                         info!("TUI service stopped, shutting down other services...");
+                                        self.stop_runners().await;
                                         task_monitor.cancel_all_monitored_tasks().await;
                                         return Ok(());
                                     }
@@ -204,6 +214,15 @@ impl Services {
         }
     }
 
+    // ------------------------------------------------------------------------------
+
+    /// Stop the runners, clearing their registry entries
+    async fn stop_runners(&self) {
+        if let Some(runners) = &self.runners {
+            runners.lock().await.stop().await;
+        }
+    }
+
     // // ------------------------------------------------------------------------------
 
     // pub async fn instances_names(&self) -> Vec<String> {
@@ -1,9 +1,17 @@
+mod registry;
 mod runner;
+use crate::payload::LineSettingsPayload;
+use crate::payload::Status;
+use crate::payload::StatusPayload;
+use crate::Topics;
+use crate::SERVER_TYPE_NAME;
 use core::task;
+use pza_toolkit::rumqtt::client::RumqttCustomAsyncClient;
 use pza_toolkit::task_monitor::TaskMonitor;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
+use tokio::sync::mpsc;
 use tokio::sync::watch;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
@@ -13,11 +21,31 @@ use tracing::info;
 
 use super::drivers::Factory as DriverFactory;
 use crate::server::config::ServerConfig;
+use crate::server::mqtt::init_client;
+use registry::InstanceRegistry;
 use runner::Runner;
 
+/// Time given to the registry client to clear the entries when the service stops
+const REGISTRY_CLEAR_TIMEOUT: TokioDuration = TokioDuration::from_millis(500);
+
 pub struct RunnersService {
     /// Just to keep the monitor alive
     _task_monitor: Arc<Mutex<Option<TaskMonitor>>>,
+
+    /// Connection publishing the registry, closed once the service stops
+    status_connection: Arc<Mutex<Option<StatusConnection>>>,
+}
+
+/// MQTT connection publishing the registry and the status of runners being restarted
+struct StatusConnection {
+    /// Client publishing the registry and the statuses
+    client: RumqttCustomAsyncClient,
+    /// Client used to disconnect once the registry entries are cleared
+    mqtt_client: rumqttc::AsyncClient,
+    /// Registry of the runners
+    registry: InstanceRegistry,
+    /// Task polling the event loop, ends once disconnected
+    event_loop_task: JoinHandle<()>,
 }
 
 impl RunnersService {
@@ -32,6 +60,19 @@ impl RunnersService {
         // Start MQTT runners for each configured device
         let factory = drivers_factory.lock().await;
         info!("Starting server runtime services...");
+
+        // Client used to publish the registry and the status of runners being restarted
+        let status_connection = Self::start_status_client(&server_config, &factory);
+        let status_client = status_connection.client.clone();
+        let broker = server_config.broker_endpoint();
+
+        // Line settings changed on each runner, reapplied when it restarts
+        let changed_settings: HashMap<String, Arc<Mutex<LineSettingsPayload>>> = server_config
+            .runner_names()
+            .into_iter()
+            .map(|name| (name, Arc::new(Mutex::new(LineSettingsPayload::request()))))
+            .collect();
+
         if let Some(devices) = &server_config.runners {
             for (name, device_config) in devices {
                 info!("Starting runner for device '{}'", name);
@@ -39,7 +80,14 @@ impl RunnersService {
                 let instance = factory.instanciate_driver(device_config.clone())?;
 
                 // Start the runner
-                let task_handle = Runner::start(name.clone(), instance).await?;
+                let task_handle = Runner::start(
+                    name.clone(),
+                    device_config.clone(),
+                    instance,
+                    broker.clone(),
+                    changed_settings[name].clone(),
+                )
+                .await?;
 
                 // Register the task with the monitor
                 task_monitor
@@ -83,6 +131,15 @@ impl RunnersService {
                                                 "Max restart attempts reached for '{}', giving up",
                                                 task_name
                                             );
+                                            Self::publish_status(
+                                                &status_client,
+                                                &task_name,
+                                                StatusPayload::from_status(Status::Stopped)
+                                                    .with_message(
+                                                        "Max restart attempts reached".to_string(),
+                                                    ),
+                                            )
+                                            .await;
                                             continue;
                                         }
 
@@ -93,6 +150,16 @@ impl RunnersService {
                                             "Scheduling restart for '{}' in {}ms (attempt {}/{})",
                                             task_name, delay_ms, *attempts, MAX_RETRIES
                                         );
+                                        Self::publish_status(
+                                            &status_client,
+                                            &task_name,
+                                            StatusPayload::from_status(Status::Reconnecting)
+                                                .with_message(format!(
+                                                    "Restart attempt {}/{} in {}ms",
+                                                    *attempts, MAX_RETRIES, delay_ms
+                                                )),
+                                        )
+                                        .await;
                                         sleep(TokioDuration::from_millis(delay_ms)).await;
 
                                         // Try to re-instantiate the driver and start a new runner
@@ -102,8 +169,14 @@ impl RunnersService {
                                             .instanciate_driver(device_cfg.clone())
                                         {
                                             Ok(instance) => {
-                                                match Runner::start(task_name.clone(), instance)
-                                                    .await
+                                                match Runner::start(
+                                                    task_name.clone(),
+                                                    device_cfg.clone(),
+                                                    instance,
+                                                    broker.clone(),
+                                                    changed_settings[&task_name].clone(),
+                                                )
+                                                .await
                                                 {
                                                     Ok(task_handle) => {
                                                         // Register replacement task with the monitor
@@ -147,8 +220,115 @@ impl RunnersService {
         Ok((
             Self {
                 _task_monitor: Arc::new(Mutex::new(Some(task_monitor))),
+                status_connection: Arc::new(Mutex::new(Some(status_connection))),
             },
             handle,
         ))
     }
+
+    // ------------------------------------------------------------------------------
+
+    /// Cancel the runners started by this service and clear their registry entries
+    pub async fn stop(&self) {
+        if let Some(mut task_monitor) = self._task_monitor.lock().await.take() {
+            task_monitor.cancel_all_monitored_tasks().await;
+        }
+        if let Some(connection) = self.status_connection.lock().await.take() {
+            connection.registry.clear().await;
+            // Queued after the clearing messages, the event loop sends them first
+            if let Err(e) = connection.mqtt_client.disconnect().await {
+                error!("Failed to disconnect the runners status client: {}", e);
+            }
+            if tokio::time::timeout(REGISTRY_CLEAR_TIMEOUT, connection.event_loop_task)
+                .await
+                .is_err()
+            {
+                error!("Timeout while clearing the registry entries");
+            }
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Create the MQTT client used to publish the registry and the status of
+    /// runners being restarted
+    ///
+    /// The registry follows the topics of every instance and is published
+    /// again on each (re)connection to the broker.
+    fn start_status_client(
+        server_config: &ServerConfig,
+        factory: &DriverFactory,
+    ) -> StatusConnection {
+        let (mqtt_client, mut event_loop) =
+            init_client(&server_config.broker_endpoint(), "runners");
+        let client = RumqttCustomAsyncClient::new(
+            mqtt_client.clone(),
+            rumqttc::QoS::AtMostOnce,
+            true,
+            SERVER_TYPE_NAME.to_string(),
+        );
+        let registry = InstanceRegistry::new(client.clone(), server_config, factory);
+
+        // Messages are handled one at a time, in the order they are received
+        let (message_sender, mut message_receiver) = mpsc::unbounded_channel();
+        let message_registry = registry.clone();
+        tokio::spawn(async move {
+            while let Some((topic, payload)) = message_receiver.recv().await {
+                message_registry.handle_message(&topic, payload).await;
+            }
+        });
+
+        let loop_client = client.clone();
+        let loop_registry = registry.clone();
+        let event_loop_task = tokio::spawn(async move {
+            loop {
+                match event_loop.poll().await {
+                    Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                        // Publications are handled in tasks, the event loop must keep polling
+                        let client = loop_client.clone();
+                        let registry = loop_registry.clone();
+                        tokio::spawn(async move {
+                            client.subscribe_to_all(InstanceRegistry::topics()).await;
+                            registry.publish().await;
+                        });
+                    }
+                    Ok(rumqttc::Event::Incoming(rumqttc::Packet::Publish(packet))) => {
+                        let _ = message_sender.send((packet.topic, packet.payload));
+                    }
+                    Ok(rumqttc::Event::Outgoing(rumqttc::Outgoing::Disconnect)) => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Runners status client connection error: {}", e);
+                        sleep(TokioDuration::from_secs(1)).await;
+                    }
+                }
+            }
+        });
+
+        StatusConnection {
+            client,
+            mqtt_client,
+            registry,
+            event_loop_task,
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Publish the status of a runner on its behalf (retained)
+    async fn publish_status(
+        client: &RumqttCustomAsyncClient,
+        runner_name: &str,
+        status: StatusPayload,
+    ) {
+        let topic = Topics::new(runner_name).status;
+        match status.to_json_bytes() {
+            Ok(bytes) => {
+                if let Err(e) = client.publish(topic, bytes.to_vec()).await {
+                    error!("Failed to publish status of '{}': {}", runner_name, e);
+                }
+            }
+            Err(e) => error!("Failed to serialize status of '{}': {}", runner_name, e),
+        }
+    }
 }
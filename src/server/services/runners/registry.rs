@@ -0,0 +1,265 @@
+use crate::payload::InstanceInfo;
+use crate::payload::Presence;
+use crate::payload::StatusPayload;
+use crate::Topics;
+use bytes::Bytes;
+use pza_toolkit::rumqtt::client::RumqttCustomAsyncClient;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::error;
+use tracing::info;
+
+use crate::server::config::ServerConfig;
+use crate::server::drivers::Factory as DriverFactory;
+
+/// Registry of the runners, one retained entry per runner on
+/// `serial-port/_instances/{name}`
+///
+/// Statuses and presences are followed through the topics of every
+/// instance, so the registry also reflects the statuses published by the
+/// runners themselves and their last-will. The entries are cleared when the
+/// service stops. A server that crashed cannot clear its entries: an entry of
+/// another server still reporting its runner online while the last-will of
+/// the runner reported it offline is cleared by the registries that see it.
+#[derive(Clone)]
+pub struct InstanceRegistry {
+    /// MQTT client used to publish the registry
+    client: RumqttCustomAsyncClient,
+    /// Instances keyed by name, emptied once the registry is cleared
+    instances: Arc<Mutex<BTreeMap<String, InstanceInfo>>>,
+    /// Instances served by other servers, or by a previous run of this one
+    foreign: Arc<Mutex<BTreeMap<String, ForeignInstance>>>,
+}
+
+/// What the registry knows about an instance it does not serve
+#[derive(Default)]
+struct ForeignInstance {
+    /// The retained entry of the instance reports its runner online
+    entry_online: bool,
+    /// Last presence of the runner, from its presence topic
+    presence: Option<Presence>,
+}
+
+impl InstanceRegistry {
+    // ------------------------------------------------------------------------------
+
+    /// Create the registry from the configured runners
+    pub fn new(
+        client: RumqttCustomAsyncClient,
+        server_config: &ServerConfig,
+        factory: &DriverFactory,
+    ) -> Self {
+        let instances = server_config
+            .runners
+            .iter()
+            .flatten()
+            .map(|(name, config)| (name.clone(), factory.instance_info(name, config)))
+            .collect();
+
+        Self {
+            client,
+            instances: Arc::new(Mutex::new(instances)),
+            foreign: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Topics followed by the registry
+    pub fn topics() -> Vec<String> {
+        vec![
+            Topics::all_status(),
+            Topics::all_presence(),
+            Topics::all_instance_entries(),
+        ]
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Handle a message of the topics followed by the registry
+    pub async fn handle_message(&self, topic: &str, payload: Bytes) {
+        if let Some(name) = Topics::instance_of_status(topic) {
+            self.handle_status(name, payload).await;
+        } else if let Some(name) = Topics::instance_of_presence(topic) {
+            self.handle_presence(name, payload).await;
+        } else if let Some(name) = Topics::instance_of_entry(topic) {
+            self.handle_entry(name, payload).await;
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Update the status of an instance from a message of its status topic
+    ///
+    /// Retained statuses of instances that are not served anymore are ignored.
+    async fn handle_status(&self, name: &str, payload: Bytes) {
+        let status = match StatusPayload::from_json_bytes(payload) {
+            Ok(status) => status.status,
+            Err(e) => {
+                error!("Invalid status payload of '{}': {}", name, e);
+                return;
+            }
+        };
+
+        let info = {
+            let mut instances = self.instances.lock().await;
+            match instances.get_mut(name) {
+                Some(info) if info.status.as_ref() != Some(&status) => {
+                    info.status = Some(status);
+                    info.clone()
+                }
+                _ => return,
+            }
+        };
+        self.publish_entry(&info).await;
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Update the presence of an instance from a message of its presence topic
+    async fn handle_presence(&self, name: &str, payload: Bytes) {
+        let presence = match Presence::from_bytes(&payload) {
+            Ok(presence) => presence,
+            Err(e) => {
+                error!("Invalid presence payload of '{}': {}", name, e);
+                return;
+            }
+        };
+
+        let info = {
+            let mut instances = self.instances.lock().await;
+            match instances.get_mut(name) {
+                Some(info) if info.presence != Some(presence) => {
+                    info.presence = Some(presence);
+                    info.clone()
+                }
+                Some(_) => return,
+                None => {
+                    drop(instances);
+                    self.update_foreign(name, |foreign| foreign.presence = Some(presence))
+                        .await;
+                    return;
+                }
+            }
+        };
+        self.publish_entry(&info).await;
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Follow the registry entries retained on the broker
+    ///
+    /// An entry of this registry cleared by another server is published
+    /// again.
+    async fn handle_entry(&self, name: &str, payload: Bytes) {
+        let info = {
+            let instances = self.instances.lock().await;
+            match instances.get(name) {
+                Some(info) if payload.is_empty() => info.clone(),
+                Some(_) => return,
+                None => {
+                    drop(instances);
+                    // Entries written before presences were reported are left alone
+                    let entry_online = !payload.is_empty()
+                        && InstanceInfo::from_json_bytes(payload)
+                            .is_ok_and(|info| info.presence == Some(Presence::Online));
+                    self.update_foreign(name, |foreign| foreign.entry_online = entry_online)
+                        .await;
+                    return;
+                }
+            }
+        };
+        self.publish_entry(&info).await;
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Update what is known about an instance of another server, clearing its
+    /// entry if it is stale
+    ///
+    /// A running server updates the entry when its runner goes offline, so an
+    /// entry still reporting an offline runner online was left by a server
+    /// that is gone. If the server only lagged behind, it publishes its entry
+    /// again when it sees it cleared.
+    async fn update_foreign(&self, name: &str, update: impl FnOnce(&mut ForeignInstance)) {
+        let stale = {
+            let mut foreign = self.foreign.lock().await;
+            let instance = foreign.entry(name.to_string()).or_default();
+            update(instance);
+            let stale = instance.entry_online && instance.presence == Some(Presence::Offline);
+            if stale {
+                instance.entry_online = false;
+            }
+            stale
+        };
+        if stale {
+            info!("Clearing the stale registry entry of '{}'", name);
+            self.clear_entry(name).await;
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Clear the entries of the runners, once the service stops
+    ///
+    /// The runners are forgotten, so their last-will does not publish their
+    /// entries again.
+    pub async fn clear(&self) {
+        let names: Vec<String> = std::mem::take(&mut *self.instances.lock().await)
+            .into_keys()
+            .collect();
+        for name in &names {
+            self.clear_entry(name).await;
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Clear the retained entry of an instance with an empty message
+    async fn clear_entry(&self, name: &str) {
+        if let Err(e) = self
+            .client
+            .publish(Topics::instance_entry(name), Vec::new())
+            .await
+        {
+            error!("Failed to clear the registry entry of '{}': {}", name, e);
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Publish the entry of every runner (retained)
+    pub async fn publish(&self) {
+        let instances: Vec<InstanceInfo> = self.instances.lock().await.values().cloned().collect();
+        for info in &instances {
+            self.publish_entry(info).await;
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Publish the entry of one runner (retained)
+    async fn publish_entry(&self, info: &InstanceInfo) {
+        match info.to_json_bytes() {
+            Ok(bytes) => {
+                if let Err(e) = self
+                    .client
+                    .publish(Topics::instance_entry(&info.name), bytes.to_vec())
+                    .await
+                {
+                    error!(
+                        "Failed to publish the registry entry of '{}': {}",
+                        info.name, e
+                    );
+                }
+            }
+            Err(e) => error!(
+                "Failed to serialize the registry entry of '{}': {}",
+                info.name, e
+            ),
+        }
+    }
+
+    // ------------------------------------------------------------------------------
+}
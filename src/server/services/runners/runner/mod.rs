@@ -1,11 +1,37 @@
+use crate::payload::AutoBaudPayload;
+use crate::payload::BytesPayload;
+use crate::payload::ErrorPayload;
+use crate::payload::LineSettingsPayload;
+use crate::payload::Presence;
+use crate::payload::Status;
+use crate::payload::StatusPayload;
+use crate::payload::TxAckPayload;
+use crate::server::config::SerialPortConfig;
+use crate::server::drivers::rx::RxPublisher;
+use crate::server::drivers::stats::PortStats;
+use crate::server::drivers::DriverContext;
 use crate::server::drivers::SerialPortDriver;
+use crate::Topics;
 use bytes::Bytes;
-use pza_serial_port_client::SERVER_TYPE_NAME;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::{any, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
 use tokio::{sync::Mutex, task::JoinHandle};
 use tracing::trace;
 
-use pza_toolkit::rumqtt::client::{init_client, RumqttCustomAsyncClient};
+use crate::server::mqtt::init_client;
+use crate::BrokerEndpoint;
+use pza_toolkit::rumqtt::client::RumqttCustomAsyncClient;
+
+/// Period of the statistics publication
+const STATS_PUBLISH_PERIOD: Duration = Duration::from_secs(2);
+
+/// Time given to the event loop to send the last publications of a stopping runner
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Delay before polling again after a broker connection error
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 /// Handler for the MQTT Runner task
@@ -24,40 +50,99 @@ pub struct Runner {
     /// Driver instance
     driver: Arc<Mutex<dyn SerialPortDriver + Send + Sync>>,
 
-    /// psu/{name}/status
-    topic_status: String,
-    /// psu/{name}/error
-    topic_error: String,
+    /// Topics of the instance
+    topics: Topics,
+
+    /// Traffic counters, shared with the driver
+    stats: Arc<PortStats>,
+
+    /// Publish structured rx chunks in addition to raw rx data
+    structured_rx: bool,
+
+    /// Set while a baud rate detection holds the port
+    autobaud_running: Arc<AtomicBool>,
+
+    /// Queue of the replies to the incoming commands, see `reply`
+    replies: mpsc::UnboundedSender<(String, Bytes)>,
+
+    /// Line settings changed through `settings/cmd`, kept by the service
+    /// across the restarts of the runner to reapply them
+    changed_settings: Arc<Mutex<LineSettingsPayload>>,
+}
+
+/// Abort a background task when dropped
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Clear the baud rate detection flag when dropped, even if the detection panics
+struct AutoBaudGuard(Arc<AtomicBool>);
 
-    /// psu/{name}/control/oe
-    topic_tx: String,
+impl Drop for AutoBaudGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
 }
 
 impl Runner {
     // --------------------------------------------------------------------------------
 
     /// Start the runner
+    ///
+    /// `changed_settings` holds the line settings changed before a restart,
+    /// they are reapplied once the driver is initialized.
     pub async fn start(
         name: String,
+        config: SerialPortConfig,
         driver: Arc<Mutex<dyn SerialPortDriver + Send + Sync>>,
+        broker: BrokerEndpoint,
+        changed_settings: Arc<Mutex<LineSettingsPayload>>,
     ) -> anyhow::Result<JoinHandle<Result<(), anyhow::Error>>> {
-        let (client, event_loop) = init_client("tttt");
+        let (client, mut event_loop) = init_client(&broker, &name);
 
+        // The broker reports the runner offline if the connection is lost
+        let topics = Topics::new(&name);
+        event_loop
+            .mqtt_options
+            .set_last_will(rumqttc::LastWill::new(
+                topics.presence.clone(),
+                Presence::Offline.to_bytes().to_vec(),
+                rumqttc::QoS::AtLeastOnce,
+                true,
+            ));
         let custom_client = RumqttCustomAsyncClient::new(
             client,
             rumqttc::QoS::AtMostOnce,
             true,
-            format!("{}/{}", SERVER_TYPE_NAME, name),
+            topics.prefix.clone(),
         );
 
+        // Replies are published by their own task, in order, so that the
+        // event loop never waits for room in the MQTT request channel
+        let (replies, mut queued_replies) = mpsc::unbounded_channel::<(String, Bytes)>();
+        let reply_client = custom_client.clone();
+        tokio::spawn(async move {
+            while let Some((topic, bytes)) = queued_replies.recv().await {
+                if let Err(e) = reply_client.publish(topic.clone(), bytes.to_vec()).await {
+                    tracing::error!("Failed to publish on '{}': {}", topic, e);
+                }
+            }
+        });
+
         // Create runner object
         let runner = Runner {
             name: name.clone(),
             driver,
-            topic_status: custom_client.topic_with_prefix("status"),
-            topic_error: custom_client.topic_with_prefix("error"),
-
-            topic_tx: custom_client.topic_with_prefix("tx"),
+            topics,
+            stats: Arc::new(PortStats::default()),
+            structured_rx: config.structured_rx.unwrap_or(false),
+            autobaud_running: Arc::new(AtomicBool::new(false)),
+            replies,
+            changed_settings,
 
             client: custom_client,
         };
@@ -71,26 +156,84 @@ impl Runner {
 
     /// The main async task loop for the MQTT runner
     async fn task_loop(mut event_loop: rumqttc::EventLoop, runner: Runner) -> anyhow::Result<()> {
+        runner
+            .publish_status(StatusPayload::from_status(Status::Initializing))
+            .await;
+
         // Subscribe to all relevant topics
         runner
             .client
-            .subscribe_to_all(vec![runner.topic_tx.clone()])
+            .subscribe_to_all(runner.topics.vec_sub_server())
+            .await;
+
+        // Describe the topic layout for clients that do not embed it
+        runner
+            .publish_payload(
+                &runner.topics.schema,
+                runner.topics.descriptor().to_json_bytes(),
+            )
             .await;
 
-        runner.initialize().await;
+        let (faults_sender, mut faults) = mpsc::unbounded_channel();
+        if let Err(e) = runner.initialize(faults_sender).await {
+            tracing::error!("Driver init failed for '{}': {}", runner.name, e);
+            runner
+                .publish_status(
+                    StatusPayload::from_status(Status::Panicking).with_panic_message(e.to_string()),
+                )
+                .await;
+            Self::flush_event_loop(&mut event_loop).await;
+            return Err(e.context("Driver init failed"));
+        }
+        let running = match runner.restore_line_settings().await {
+            Ok(()) => StatusPayload::from_status(Status::Running),
+            Err(message) => StatusPayload::from_status(Status::Running).with_message(message),
+        };
+        runner.publish_status(running).await;
+
+        // Publish statistics until the runner stops
+        let _stats_task = AbortOnDrop(tokio::spawn(Self::stats_loop(
+            runner.client.clone(),
+            runner.driver.clone(),
+            runner.stats.clone(),
+            runner.topics.stats.clone(),
+        )));
 
+        let mut connected_once = false;
         loop {
-            while let Ok(event) = event_loop.poll().await {
-                match event {
-                    rumqttc::Event::Incoming(incoming) => match incoming {
+            tokio::select! {
+                event = event_loop.poll() => match event {
+                    Ok(rumqttc::Event::Incoming(incoming)) => match incoming {
                         rumqttc::Packet::Publish(packet) => {
                             let topic = packet.topic;
                             let payload = packet.payload;
                             runner.handle_incoming_message(&topic, payload).await;
                         }
+                        rumqttc::Packet::ConnAck(_) => {
+                            runner
+                                .reply(&runner.topics.presence, Ok(Presence::Online.to_bytes()));
+                            if connected_once {
+                                runner.stats.record_reconnection();
+                            }
+                            connected_once = true;
+                        }
                         _ => {}
                     },
-                    rumqttc::Event::Outgoing(_outgoing) => {}
+                    Ok(rumqttc::Event::Outgoing(_outgoing)) => {}
+                    Err(e) => {
+                        tracing::warn!("Runner '{}' broker connection error: {}", runner.name, e);
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                },
+
+                // The driver lost its port, stop so that the service restarts the runner
+                Some(fault) = faults.recv() => {
+                    tracing::error!("Runner '{}' driver fault: {}", runner.name, fault);
+                    let disconnected =
+                        StatusPayload::from_status(Status::Disconnected).with_message(fault.clone());
+                    runner.reply(&runner.topics.status, disconnected.to_json_bytes());
+                    Self::flush_event_loop(&mut event_loop).await;
+                    return Err(anyhow::anyhow!(fault));
                 }
             }
         }
@@ -98,14 +241,114 @@ impl Runner {
 
     // --------------------------------------------------------------------------------
 
+    /// Drive the event loop for a short while so queued publications reach the broker
+    async fn flush_event_loop(event_loop: &mut rumqttc::EventLoop) {
+        let _ = tokio::time::timeout(FLUSH_TIMEOUT, async {
+            while event_loop.poll().await.is_ok() {}
+        })
+        .await;
+    }
+
+    // --------------------------------------------------------------------------------
+
     /// Initialize the runner (if needed)
-    async fn initialize(&self) {
+    async fn initialize(&self, faults: mpsc::UnboundedSender<String>) -> anyhow::Result<()> {
         let mut driver = self.driver.lock().await;
 
+        let rx_publisher = RxPublisher::new(
+            self.client.clone(),
+            self.name.clone(),
+            &self.topics,
+            self.structured_rx,
+            self.stats.clone(),
+        );
+
         driver
-            .initialize(self.client.clone())
+            .initialize(DriverContext {
+                client: self.client.clone(),
+                topics: self.topics.clone(),
+                stats: self.stats.clone(),
+                rx_publisher,
+                faults,
+            })
             .await
-            .expect("Driver init failed");
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Reapply the line settings changed before a restart of the runner
+    ///
+    /// The driver opens the port with the configured settings, the changes
+    /// made through `settings/cmd` would be lost silently otherwise. On
+    /// failure, returns the message telling they were reset.
+    async fn restore_line_settings(&self) -> Result<(), String> {
+        let changes = self.changed_settings.lock().await.clone();
+        if changes.is_read_only() {
+            return Ok(());
+        }
+
+        let result = {
+            let mut driver = self.driver.lock().await;
+            driver.set_line_settings(changes).await
+        };
+        match result {
+            Ok(applied) => {
+                tracing::info!(
+                    "Runner '{}' line settings restored: {:?}",
+                    self.name,
+                    applied
+                );
+                self.publish_payload(&self.topics.settings, applied.to_json_bytes())
+                    .await;
+                Ok(())
+            }
+            Err(e) => {
+                let message = format!(
+                    "Line settings reset to the configured ones, the changes could not be reapplied: {}",
+                    e
+                );
+                tracing::error!("Runner '{}': {}", self.name, message);
+                let error = ErrorPayload::from_message(message.clone());
+                self.publish_payload(&self.topics.error, error.to_json_bytes())
+                    .await;
+                Err(message)
+            }
+        }
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Publish the lifecycle status of the runner (retained)
+    async fn publish_status(&self, status: StatusPayload) {
+        self.publish_payload(&self.topics.status, status.to_json_bytes())
+            .await;
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Periodically publish the port statistics (retained)
+    async fn stats_loop(
+        client: RumqttCustomAsyncClient,
+        driver: Arc<Mutex<dyn SerialPortDriver + Send + Sync>>,
+        stats: Arc<PortStats>,
+        topic_stats: String,
+    ) {
+        let mut interval = tokio::time::interval(STATS_PUBLISH_PERIOD);
+        loop {
+            interval.tick().await;
+
+            let line = driver.lock().await.line_counters().await;
+            let payload = match stats.snapshot(line).to_json_bytes() {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::error!("Failed to serialize stats: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = client.publish(topic_stats.clone(), payload.to_vec()).await {
+                tracing::error!("Failed to publish stats: {}", e);
+            }
+        }
     }
 
     // --------------------------------------------------------------------------------
@@ -114,13 +357,259 @@ impl Runner {
     /// TODO => handle error return here
     async fn handle_incoming_message(&self, topic: &String, payload: Bytes) {
         // ON/OFF Output Enable
-        if topic.eq(&self.topic_tx) {
+        if topic.eq(&self.topics.tx) {
             trace!("Received TX command on topic {}: {:?}", topic, payload);
+            if self.reject_during_autobaud(None) {
+                return;
+            }
             let mut driver = self.driver.lock().await;
 
             if let Err(e) = driver.send(payload).await {
                 tracing::error!("Error sending data to serial port: {}", e);
             }
+        } else if topic.eq(&self.topics.tx_confirmed) {
+            self.handle_tx_confirmed_command(payload).await;
+        } else if topic.eq(&self.topics.autobaud_cmd) {
+            self.handle_autobaud_command(payload).await;
+        } else if topic.eq(&self.topics.settings_cmd) {
+            self.handle_settings_command(payload).await;
+        }
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Send the bytes of a confirmed tx command, then acknowledge it
+    ///
+    /// The acknowledgement (or the error) is published from a separate task
+    /// once the driver reports the bytes written, so the event loop keeps running.
+    async fn handle_tx_confirmed_command(&self, payload: Bytes) {
+        let command = match BytesPayload::from_json_bytes(payload) {
+            Ok(command) => command,
+            Err(e) => {
+                let error = ErrorPayload::from_message(format!("Invalid tx command: {}", e));
+                self.reply(&self.topics.error, error.to_json_bytes());
+                return;
+            }
+        };
+
+        trace!(
+            "Received confirmed TX command {}: {:?}",
+            command.pza_id,
+            command.data
+        );
+        if self.reject_during_autobaud(Some(command.pza_id.clone())) {
+            return;
+        }
+        let len = command.data.len();
+        let completion = {
+            let mut driver = self.driver.lock().await;
+            driver.send_confirmed(command.data).await
+        };
+
+        let completion = match completion {
+            Ok(completion) => completion,
+            Err(e) => {
+                tracing::error!("Error sending data to serial port: {}", e);
+                let error = ErrorPayload::from_message_as_response(e.to_string(), command.pza_id);
+                self.reply(&self.topics.error, error.to_json_bytes());
+                return;
+            }
+        };
+
+        let client = self.client.clone();
+        let topic_tx_ack = self.topics.tx_ack.clone();
+        let topic_error = self.topics.error.clone();
+        tokio::spawn(async move {
+            let (topic, bytes) = match completion.await {
+                Ok(Ok(())) => (
+                    topic_tx_ack,
+                    TxAckPayload::new(command.pza_id, len).to_json_bytes(),
+                ),
+                Ok(Err(e)) => (
+                    topic_error,
+                    ErrorPayload::from_message_as_response(e.to_string(), command.pza_id)
+                        .to_json_bytes(),
+                ),
+                Err(_) => (
+                    topic_error,
+                    ErrorPayload::from_message_as_response(
+                        "Serial port task stopped before writing the data".to_string(),
+                        command.pza_id,
+                    )
+                    .to_json_bytes(),
+                ),
+            };
+            match bytes {
+                Ok(bytes) => {
+                    if let Err(e) = client.publish(topic, bytes.to_vec()).await {
+                        tracing::error!("Failed to publish tx acknowledgement: {}", e);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to serialize tx acknowledgement: {}", e),
+            }
+        });
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Publish a serialized payload, logging failures
+    async fn publish_payload(&self, topic: &str, bytes: anyhow::Result<Bytes>) {
+        match bytes {
+            Ok(bytes) => {
+                if let Err(e) = self.client.publish(topic.to_string(), bytes.to_vec()).await {
+                    tracing::error!("Failed to publish on '{}': {}", topic, e);
+                }
+            }
+            Err(e) => tracing::error!("Failed to serialize payload for '{}': {}", topic, e),
+        }
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Queue a serialized payload for the reply task, logging failures
+    ///
+    /// Used for everything published while handling an incoming packet: a
+    /// burst of commands would otherwise fill the request channel while the
+    /// event loop waits on it, and deadlock the runner.
+    fn reply(&self, topic: &str, bytes: anyhow::Result<Bytes>) {
+        match bytes {
+            Ok(bytes) => {
+                if self.replies.send((topic.to_string(), bytes)).is_err() {
+                    tracing::error!("Failed to queue a reply on '{}': reply task stopped", topic);
+                }
+            }
+            Err(e) => tracing::error!("Failed to serialize payload for '{}': {}", topic, e),
+        }
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Publish an error for a command received while a baud rate detection runs
+    ///
+    /// The detection holds the port for several seconds, so the command is
+    /// rejected instead of waiting for it. Returns whether it was rejected.
+    fn reject_during_autobaud(&self, pza_id: Option<String>) -> bool {
+        if !self.autobaud_running.load(Ordering::SeqCst) {
+            return false;
+        }
+        let message = "Baud rate detection in progress, command rejected".to_string();
+        tracing::warn!("Runner '{}': {}", self.name, message);
+        let error = match pza_id {
+            Some(pza_id) => ErrorPayload::from_message_as_response(message, pza_id),
+            None => ErrorPayload::from_message(message),
+        };
+        self.reply(&self.topics.error, error.to_json_bytes());
+        true
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Start an on-demand baud rate detection, its result is published once done
+    ///
+    /// The detection runs in a separate task so the event loop keeps running;
+    /// until it ends, the commands that need the port are rejected.
+    async fn handle_autobaud_command(&self, payload: Bytes) {
+        // An empty payload is accepted, the command then gets a fresh pza_id
+        let pza_id = AutoBaudPayload::from_json_bytes(payload)
+            .map(|request| request.pza_id)
+            .unwrap_or_else(|_| crate::payload::generate_pza_id());
+
+        if self
+            .autobaud_running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            let error = ErrorPayload::from_message_as_response(
+                "Baud rate detection already in progress".to_string(),
+                pza_id,
+            );
+            self.reply(&self.topics.error, error.to_json_bytes());
+            return;
+        }
+        let guard = AutoBaudGuard(self.autobaud_running.clone());
+
+        let driver = self.driver.clone();
+        let client = self.client.clone();
+        let topic_autobaud = self.topics.autobaud.clone();
+        let topic_error = self.topics.error.clone();
+        tokio::spawn(async move {
+            let result = {
+                let mut driver = driver.lock().await;
+                driver.detect_baud_rate().await
+            };
+            drop(guard);
+
+            let (topic, bytes) = match result {
+                Ok(detection) => (
+                    topic_autobaud,
+                    detection.with_pza_id(pza_id).to_json_bytes(),
+                ),
+                Err(e) => {
+                    tracing::error!("Baud rate detection failed: {}", e);
+                    (
+                        topic_error,
+                        ErrorPayload::from_message_as_response(e.to_string(), pza_id)
+                            .to_json_bytes(),
+                    )
+                }
+            };
+            match bytes {
+                Ok(bytes) => {
+                    if let Err(e) = client.publish(topic, bytes.to_vec()).await {
+                        tracing::error!("Failed to publish baud rate detection result: {}", e);
+                    }
+                }
+                Err(e) => tracing::error!("Failed to serialize baud rate detection result: {}", e),
+            }
+        });
+    }
+
+    // --------------------------------------------------------------------------------
+
+    /// Read or change the line settings, then publish the applied ones
+    async fn handle_settings_command(&self, payload: Bytes) {
+        let request = match LineSettingsPayload::from_json_bytes(payload) {
+            Ok(request) => request,
+            Err(e) => {
+                let error =
+                    ErrorPayload::from_message(format!("Invalid line settings command: {}", e));
+                self.reply(&self.topics.error, error.to_json_bytes());
+                return;
+            }
+        };
+        let pza_id = request.pza_id.clone();
+        if self.reject_during_autobaud(Some(pza_id.clone())) {
+            return;
         }
+
+        let result = {
+            let mut driver = self.driver.lock().await;
+            if request.is_read_only() {
+                driver.line_settings().await
+            } else {
+                let result = driver.set_line_settings(request.clone()).await;
+                if result.is_ok() {
+                    let mut changed = self.changed_settings.lock().await;
+                    *changed = changed.clone().merge(&request);
+                }
+                result
+            }
+        };
+
+        let (topic, bytes) = match result {
+            Ok(applied) => (
+                &self.topics.settings,
+                applied.with_pza_id(pza_id).to_json_bytes(),
+            ),
+            Err(e) => {
+                tracing::error!("Line settings command failed: {}", e);
+                (
+                    &self.topics.error,
+                    ErrorPayload::from_message_as_response(e.to_string(), pza_id).to_json_bytes(),
+                )
+            }
+        };
+
+        self.reply(topic, bytes);
     }
 }
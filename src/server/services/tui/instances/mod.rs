@@ -0,0 +1,100 @@
+/// Instances widget for TUI display
+///
+/// Lists the serial port instances with their presence and lifecycle status.
+use crate::payload::Presence;
+use crate::BrokerEndpoint;
+use crate::SerialPortClient;
+use ratatui::layout::Rect;
+use ratatui::prelude::Buffer;
+use ratatui::style::Color;
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::text::Span;
+use ratatui::widgets::Block;
+use ratatui::widgets::Borders;
+use ratatui::widgets::Paragraph;
+use ratatui::widgets::Widget;
+
+// ================
+
+/// Widget that displays the presence and status of each instance
+pub struct InstancesWidget {
+    /// One client per instance
+    clients: Vec<SerialPortClient>,
+}
+
+// ================
+
+impl InstancesWidget {
+    // ------------------------------------------------------------------------------
+
+    /// Create a new instances widget, connecting a client to each instance
+    pub fn new(mut instance_names: Vec<String>, broker: BrokerEndpoint) -> anyhow::Result<Self> {
+        instance_names.sort();
+        let clients = instance_names
+            .into_iter()
+            .map(|name| {
+                SerialPortClient::builder()
+                    .with_power_supply_name(name)
+                    .with_ip(broker.clone())
+                    .build()
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { clients })
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// True once the presence of at least one instance has been received
+    pub fn is_ready(&self) -> bool {
+        self.clients
+            .iter()
+            .any(|client| client.presence().is_some())
+    }
+
+    // ------------------------------------------------------------------------------
+
+    /// Build the line describing one instance
+    fn instance_line(client: &SerialPortClient) -> Line<'static> {
+        let (presence, color) = match client.presence() {
+            Some(Presence::Online) => ("online", Color::Green),
+            Some(Presence::Offline) => ("offline", Color::Red),
+            None => ("unknown", Color::DarkGray),
+        };
+        let status = client
+            .status()
+            .map(|status| format!("{:?}", status.status))
+            .unwrap_or_else(|| "-".to_string());
+
+        Line::from(vec![
+            Span::raw(format!("{:<24}", client.instance_name)),
+            Span::styled(format!("{:<10}", presence), Style::default().fg(color)),
+            Span::raw(status),
+        ])
+    }
+
+    // ------------------------------------------------------------------------------
+}
+
+// ================
+
+impl Widget for &InstancesWidget {
+    // ------------------------------------------------------------------------------
+
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::default()
+            .title("Instances")
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::Cyan));
+
+        let lines: Vec<Line> = self
+            .clients
+            .iter()
+            .map(InstancesWidget::instance_line)
+            .collect();
+
+        Paragraph::new(lines).block(block).render(area, buf);
+    }
+
+    // ------------------------------------------------------------------------------
+}
@@ -1,3 +1,4 @@
+mod instances;
 mod loading;
 
 use std::any;
@@ -30,6 +31,8 @@ use ratatui::widgets::Borders;
 use ratatui::widgets::Paragraph;
 use ratatui::Terminal;
 
+use crate::BrokerEndpoint;
+use instances::InstancesWidget;
 use loading::LoadingWidget;
 use tokio::task::JoinHandle;
 
@@ -46,10 +49,13 @@ pub struct TuiService {
 }
 
 impl TuiService {
-    /// Starts the TUI service in a separate task
-    pub fn start() -> JoinHandle<Result<(), anyhow::Error>> {
+    /// Starts the TUI service in a separate task, following the instances through `broker`
+    pub fn start(
+        instance_names: Vec<String>,
+        broker: BrokerEndpoint,
+    ) -> JoinHandle<Result<(), anyhow::Error>> {
         println!("Starting TUI service...");
-        let handle = tokio::spawn(Self::render_loop());
+        let handle = tokio::spawn(Self::render_loop(instance_names, broker));
 
         handle
     }
@@ -89,9 +95,13 @@ impl TuiService {
         self.should_quit
     }
 
-    async fn render_loop() -> anyhow::Result<()> {
+    async fn render_loop(
+        instance_names: Vec<String>,
+        broker: BrokerEndpoint,
+    ) -> anyhow::Result<()> {
         let mut app = TuiService { should_quit: false };
         let mut loading_widget = LoadingWidget::new("Please wait, TUI is initializing...");
+        let instances_widget = InstancesWidget::new(instance_names, broker)?;
 
         // Setup terminal
         let mut stdout = io::stdout();
@@ -111,6 +121,12 @@ impl TuiService {
                     ])
                     .split(f.area());
 
+                // Show the instances once their presence is known
+                if instances_widget.is_ready() {
+                    f.render_widget(&instances_widget, chunks[0]);
+                    return;
+                }
+
                 // Render loading widget with basic content
                 let widget_message = loading_widget.get_message().to_string();
                 let widget_copy = LoadingWidget::new(widget_message);
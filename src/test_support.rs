@@ -0,0 +1,289 @@
+//! Fixture for integration tests
+//!
+//! Starts the embedded broker on a random port and runners from in-memory
+//! configurations, then hands out clients already connected to them.
+//!
+//! ```ignore
+//! let bench = TestBench::builder().with_emulator("dut").start().await?;
+//! let client = bench.client("dut").unwrap();
+//! client.send(Bytes::from_static(b"AT\r\n")).await?;
+//! client.read_until(b"\r\n", Duration::from_secs(1)).await?;
+//! ```
+use crate::server::config::BaudRateConfig;
+use crate::server::config::IPEndpointConfig;
+use crate::server::config::SerialPortConfig;
+use crate::server::config::SerialPortEndpointConfig;
+use crate::server::config::ServerConfig;
+use crate::server::drivers::Factory as DriverFactory;
+use crate::server::services::runners::RunnersService;
+use crate::BrokerEndpoint;
+use crate::SerialPortClient;
+use crate::SerialPortClientBuilder;
+use pza_toolkit::rumqtt::broker::start_broker_in_thread;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex as StdMutex;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/// Time given to the runners to come up by default
+const DEFAULT_START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Address the test broker listens on
+const BROKER_ADDR: &str = "127.0.0.1";
+
+/// Ports tried before giving up on starting the broker
+const BROKER_START_ATTEMPTS: usize = 5;
+
+/// Time given to the broker to accept connections on its port
+const BROKER_READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Ports given to the brokers of this process, never handed out twice
+static USED_PORTS: StdMutex<Option<HashSet<u16>>> = StdMutex::new(None);
+
+/// Builder of a `TestBench`
+pub struct TestBenchBuilder {
+    /// Runners to start, keyed by instance name
+    runners: HashMap<String, SerialPortConfig>,
+
+    /// Maximum time to wait for the clients to be connected and the runners running
+    start_timeout: Duration,
+}
+
+impl Default for TestBenchBuilder {
+    fn default() -> Self {
+        Self {
+            runners: HashMap::new(),
+            start_timeout: DEFAULT_START_TIMEOUT,
+        }
+    }
+}
+
+impl TestBenchBuilder {
+    // ------------------------------------------------------------------------
+
+    /// Add a runner with its configuration
+    pub fn with_runner<A: Into<String>>(mut self, name: A, config: SerialPortConfig) -> Self {
+        self.runners.insert(name.into(), config);
+        self
+    }
+
+    /// Add an emulator runner, the data sent to it is looped back on rx
+    pub fn with_emulator<A: Into<String>>(self, name: A) -> Self {
+        self.with_runner(name, TestBench::emulator_config())
+    }
+
+    /// Add a runner on an existing port, a PTY for example
+    pub fn with_port<A: Into<String>, B: Into<String>>(
+        self,
+        name: A,
+        port: B,
+        baud_rate: u32,
+    ) -> Self {
+        self.with_runner(
+            name,
+            SerialPortConfig {
+                model: "standard".to_string(),
+                description: None,
+                endpoint: Some(SerialPortEndpointConfig {
+                    name: Some(port.into()),
+                    usb: None,
+                    baud_rate: Some(BaudRateConfig::Fixed(baud_rate)),
+                }),
+                auto_baud: None,
+                structured_rx: None,
+                mcp_buffer_size: None,
+                test_messages: None,
+            },
+        )
+    }
+
+    /// Set the maximum time to wait for the runners to be ready
+    pub fn with_start_timeout(mut self, timeout: Duration) -> Self {
+        self.start_timeout = timeout;
+        self
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Start the broker, the runners and their clients
+    ///
+    /// Returns once every client is connected and every runner is running.
+    pub async fn start(self) -> anyhow::Result<TestBench> {
+        let mut server_config = ServerConfig::default();
+        server_config.tui.enable = Some(false);
+        server_config.mcp.enable = false;
+        server_config.broker.use_builtin = Some(true);
+        server_config.runners = Some(self.runners.clone());
+        server_config.validate()?;
+
+        let broker = start_broker(&mut server_config).await?;
+
+        let factory = Arc::new(Mutex::new(DriverFactory::initialize()));
+        let (runners, monitor) = RunnersService::start(server_config, factory).await?;
+
+        // Owned by the bench from here, so a failure below still tears down the runners
+        let mut bench = TestBench {
+            broker: broker.clone(),
+            runners: Some(runners),
+            monitor,
+            clients: HashMap::new(),
+        };
+
+        for name in self.runners.keys() {
+            let client = SerialPortClientBuilder::default()
+                .with_power_supply_name(name.clone())
+                .with_ip(broker.clone())
+                .build()?;
+            tokio::time::timeout(self.start_timeout, client.connected())
+                .await
+                .map_err(|_| anyhow::anyhow!("Client of '{}' did not connect in time", name))??;
+            client.wait_until_running(self.start_timeout).await?;
+            bench.clients.insert(name.clone(), client);
+        }
+
+        Ok(bench)
+    }
+}
+
+/// Broker and runners started for a test, stopped when dropped
+///
+/// The runner tasks are cancelled on drop. The embedded broker runs on its
+/// own thread and cannot be stopped, it stays on its random port until the
+/// test process exits.
+pub struct TestBench {
+    /// Endpoint of the embedded broker
+    broker: BrokerEndpoint,
+
+    /// Runners service, taken on shutdown
+    runners: Option<RunnersService>,
+
+    /// Task restarting the failed runners
+    monitor: JoinHandle<anyhow::Result<()>>,
+
+    /// Connected clients, keyed by instance name
+    clients: HashMap<String, SerialPortClient>,
+}
+
+impl TestBench {
+    // ------------------------------------------------------------------------
+
+    /// Create a builder for a test bench
+    pub fn builder() -> TestBenchBuilder {
+        TestBenchBuilder::default()
+    }
+
+    /// Configuration of an emulator runner
+    pub fn emulator_config() -> SerialPortConfig {
+        SerialPortConfig {
+            model: "emulator".to_string(),
+            description: None,
+            endpoint: None,
+            auto_baud: None,
+            structured_rx: None,
+            mcp_buffer_size: None,
+            // The periodic message would mix with the answers read by the tests
+            test_messages: Some(false),
+        }
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Endpoint of the embedded broker, to build additional clients
+    pub fn broker(&self) -> BrokerEndpoint {
+        self.broker.clone()
+    }
+
+    /// Connected client of an instance
+    pub fn client(&self, name: &str) -> Option<&SerialPortClient> {
+        self.clients.get(name)
+    }
+
+    /// Builder of an additional client on the embedded broker
+    pub fn client_builder<A: Into<String>>(&self, name: A) -> SerialPortClientBuilder {
+        SerialPortClientBuilder::default()
+            .with_power_supply_name(name)
+            .with_ip(self.broker())
+    }
+
+    // ------------------------------------------------------------------------
+
+    /// Stop the runners and wait for their tasks to be cancelled
+    pub async fn shutdown(mut self) {
+        self.monitor.abort();
+        if let Some(runners) = self.runners.take() {
+            runners.stop().await;
+        }
+    }
+}
+
+impl Drop for TestBench {
+    fn drop(&mut self) {
+        self.monitor.abort();
+        if let Some(runners) = self.runners.take() {
+            // Tasks of a test runtime are dropped with it if this never runs
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn(async move { runners.stop().await });
+            }
+        }
+    }
+}
+
+/// Find a free TCP port for the broker, not given to another bench yet
+///
+/// The port is released before the broker binds it, another process can
+/// take it in between: `start_broker` retries on another port then.
+fn free_port() -> anyhow::Result<u16> {
+    let mut used = USED_PORTS.lock().unwrap_or_else(|e| e.into_inner());
+    let used = used.get_or_insert_with(HashSet::new);
+    loop {
+        let listener = std::net::TcpListener::bind((BROKER_ADDR, 0))?;
+        let port = listener.local_addr()?.port();
+        if used.insert(port) {
+            return Ok(port);
+        }
+    }
+}
+
+/// Start the embedded broker on a free port, set in the configuration
+async fn start_broker(server_config: &mut ServerConfig) -> anyhow::Result<BrokerEndpoint> {
+    for _ in 0..BROKER_START_ATTEMPTS {
+        let broker = BrokerEndpoint::new(BROKER_ADDR, free_port()?);
+        server_config.broker.tcp = Some(IPEndpointConfig {
+            addr: broker.addr.clone(),
+            port: broker.port,
+        });
+        if let Err(e) = start_broker_in_thread(server_config.broker.clone()) {
+            tracing::warn!("Test broker not started on port {}: {}", broker.port, e);
+            continue;
+        }
+        if broker_ready(&broker).await {
+            return Ok(broker);
+        }
+        tracing::warn!(
+            "Test broker not listening on port {}, retrying",
+            broker.port
+        );
+    }
+    Err(anyhow::anyhow!(
+        "Test broker not started after {} attempts",
+        BROKER_START_ATTEMPTS
+    ))
+}
+
+/// Wait until the broker accepts TCP connections
+async fn broker_ready(broker: &BrokerEndpoint) -> bool {
+    let connect = async {
+        loop {
+            match tokio::net::TcpStream::connect((broker.addr.as_str(), broker.port)).await {
+                Ok(_) => return,
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        }
+    };
+    tokio::time::timeout(BROKER_READY_TIMEOUT, connect)
+        .await
+        .is_ok()
+}
@@ -0,0 +1,31 @@
+//! Integration tests of the blocking client against an emulated runner
+use pza_serial_port_client::test_support::TestBench;
+use pza_serial_port_client::BlockingSerialPortClient;
+use std::time::Duration;
+
+/// Timeout of the operations of the tests
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn blocking_client_reads_the_looped_back_data() -> anyhow::Result<()> {
+    // The bench runs on its own runtime, the blocking client must not be
+    // called from inside it
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let bench = runtime.block_on(TestBench::builder().with_emulator("dut").start())?;
+
+    let client = BlockingSerialPortClient::connect(bench.client_builder("dut"), TIMEOUT)?;
+    client.send(b"hello\r\n")?;
+    assert_eq!(client.read_line(TIMEOUT)?, "hello");
+
+    client.send(b"version 1.2 ready\n")?;
+    let result = client.expect(r"version (?P<major>\d+)\.(\d+)", TIMEOUT)?;
+    assert_eq!(result.matched, "version 1.2");
+    assert_eq!(result.named.get("major").map(String::as_str), Some("1"));
+    assert_eq!(client.read_line(TIMEOUT)?, " ready");
+
+    drop(client);
+    runtime.block_on(bench.shutdown());
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! Integration tests of the client against emulated runners
+use bytes::Bytes;
+use pza_serial_port_client::payload::LineSettingsPayload;
+use pza_serial_port_client::payload::Parity;
+use pza_serial_port_client::payload::Presence;
+use pza_serial_port_client::test_support::TestBench;
+use pza_serial_port_client::SerialPortClient;
+use std::time::Duration;
+
+/// Timeout of the operations of the tests
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transact_reads_the_looped_back_answer() -> anyhow::Result<()> {
+    let bench = TestBench::builder().with_emulator("dut").start().await?;
+    let client = bench.client("dut").unwrap();
+
+    let answer = client
+        .transact(Bytes::from_static(b"ping\n"), b"\n", TIMEOUT)
+        .await?;
+    assert_eq!(answer, Bytes::from_static(b"ping\n"));
+
+    bench.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_confirmed_is_acknowledged() -> anyhow::Result<()> {
+    let bench = TestBench::builder().with_emulator("dut").start().await?;
+    let client = bench.client("dut").unwrap();
+
+    client
+        .send_confirmed(Bytes::from_static(b"hello\n"), TIMEOUT)
+        .await?;
+    assert_eq!(client.read_line(TIMEOUT).await?, "hello");
+
+    bench.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn line_settings_are_applied_by_the_driver() -> anyhow::Result<()> {
+    let bench = TestBench::builder().with_emulator("dut").start().await?;
+    let client = bench.client("dut").unwrap();
+
+    let changes = LineSettingsPayload::request()
+        .with_baud_rate(57600)
+        .with_parity(Parity::Even);
+    let applied = client.set_line_settings(changes, TIMEOUT).await?;
+    assert_eq!(applied.baud_rate, Some(57600));
+    assert_eq!(applied.parity, Some(Parity::Even));
+
+    let read = client.line_settings(TIMEOUT).await?;
+    assert_eq!(read.baud_rate, Some(57600));
+    assert_eq!(read.data_bits, Some(8));
+
+    bench.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn instances_do_not_see_each_other_data() -> anyhow::Result<()> {
+    let bench = TestBench::builder()
+        .with_emulator("a")
+        .with_emulator("b")
+        .start()
+        .await?;
+    let a = bench.client("a").unwrap();
+    let b = bench.client("b").unwrap();
+
+    a.send(Bytes::from_static(b"to-a\n")).await?;
+    b.send(Bytes::from_static(b"to-b\n")).await?;
+    assert_eq!(a.read_line(TIMEOUT).await?, "to-a");
+    assert_eq!(b.read_line(TIMEOUT).await?, "to-b");
+
+    bench.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn additional_clients_share_the_instance() -> anyhow::Result<()> {
+    let bench = TestBench::builder().with_emulator("dut").start().await?;
+    let sender = bench.client("dut").unwrap();
+    let observer: SerialPortClient = bench.client_builder("dut").build()?;
+    observer.connected().await?;
+    observer.wait_until_running(TIMEOUT).await?;
+
+    sender.send(Bytes::from_static(b"shared\n")).await?;
+    assert_eq!(observer.read_line(TIMEOUT).await?, "shared");
+
+    bench.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn discover_lists_every_instance() -> anyhow::Result<()> {
+    let bench = TestBench::builder()
+        .with_emulator("a")
+        .with_emulator("b")
+        .start()
+        .await?;
+
+    let instances = SerialPortClient::discover(bench.broker(), TIMEOUT).await?;
+    let names: Vec<&str> = instances.iter().map(|info| info.name.as_str()).collect();
+    assert_eq!(names, ["a", "b"]);
+    assert!(instances.iter().all(|info| info.model == "emulator"));
+
+    bench.shutdown().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn registry_entries_are_cleared_on_shutdown() -> anyhow::Result<()> {
+    let bench = TestBench::builder().with_emulator("a").start().await?;
+    let broker = bench.broker();
+
+    // The presence of the runner reaches the registry after its status
+    let deadline = tokio::time::Instant::now() + TIMEOUT;
+    loop {
+        let instances = SerialPortClient::discover(broker.clone(), TIMEOUT).await?;
+        if instances[0].presence == Some(Presence::Online) {
+            break;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "presence not reported"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    bench.shutdown().await;
+    assert!(
+        SerialPortClient::discover(broker, Duration::from_millis(500))
+            .await
+            .is_err()
+    );
+    Ok(())
+}